@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS job_run;
+
+DROP TABLE IF EXISTS scheduled_job;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TABLE scheduled_job (
+    id VARCHAR PRIMARY KEY,
+    account_id VARCHAR NOT NULL,
+    command JSONB NOT NULL,
+    run_at TIMESTAMPTZ,
+    cron TEXT,
+    next_run TIMESTAMPTZ,
+    enabled BOOL NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX scheduled_job_next_run
+    ON scheduled_job (next_run);
+CREATE INDEX scheduled_job_account
+    ON scheduled_job (account_id);
+
+CREATE TABLE job_run (
+    id VARCHAR PRIMARY KEY,
+    job_id VARCHAR NOT NULL,
+    ran_at TIMESTAMPTZ NOT NULL,
+    success BOOL NOT NULL,
+    error TEXT
+);
+
+CREATE INDEX job_run_job
+    ON job_run (job_id, ran_at);
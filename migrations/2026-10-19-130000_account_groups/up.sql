@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE account_group (
+    id VARCHAR PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (created_by, name)
+);
+
+CREATE TABLE account_group_member (
+    group_id VARCHAR NOT NULL,
+    account_id VARCHAR NOT NULL,
+    PRIMARY KEY (group_id, account_id)
+);
+
+CREATE INDEX account_group_member_account
+    ON account_group_member (account_id);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE controlled_account
+    ADD COLUMN enabled BOOL NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE controlled_account
+    DROP COLUMN IF EXISTS dry_run;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE controlled_account
+    ADD COLUMN token_kind VARCHAR NOT NULL DEFAULT 'user';
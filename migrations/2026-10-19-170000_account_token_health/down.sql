@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE controlled_account
+    DROP COLUMN IF EXISTS token_status,
+    DROP COLUMN IF EXISTS last_checked,
+    DROP COLUMN IF EXISTS last_error;
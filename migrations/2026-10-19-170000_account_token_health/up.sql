@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE controlled_account
+    ADD COLUMN token_status VARCHAR NOT NULL DEFAULT 'unchecked',
+    ADD COLUMN last_checked TIMESTAMPTZ,
+    ADD COLUMN last_error VARCHAR;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE webhook (
+    id VARCHAR PRIMARY KEY,
+    name TEXT NOT NULL,
+    discord_id VARCHAR NOT NULL,
+    encrypted_url VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (created_by, name)
+);
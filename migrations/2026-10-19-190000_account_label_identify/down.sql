@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE controlled_account
+    DROP COLUMN IF EXISTS label,
+    DROP COLUMN IF EXISTS identify_options;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE controlled_account
+    ADD COLUMN label TEXT,
+    ADD COLUMN identify_options JSONB;
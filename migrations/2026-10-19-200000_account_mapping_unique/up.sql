@@ -0,0 +1,8 @@
+-- Your SQL goes here
+DELETE FROM account_mapping a
+    USING account_mapping b
+    WHERE a.controlled_internal_id = b.controlled_internal_id
+        AND a.mapped_discord_id = b.mapped_discord_id
+        AND a.id > b.id;
+
+CREATE UNIQUE INDEX account_mapping_account_mapped_idx ON account_mapping (controlled_internal_id, mapped_discord_id);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE invite (
+    code VARCHAR PRIMARY KEY,
+    created_by VARCHAR NOT NULL,
+    email TEXT,
+    flags BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_by VARCHAR,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX invite_created_by
+    ON invite (created_by);
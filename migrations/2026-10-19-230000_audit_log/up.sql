@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE audit_log (
+    id VARCHAR PRIMARY KEY,
+    actor_id VARCHAR REFERENCES users (id) ON DELETE SET NULL,
+    action TEXT NOT NULL,
+    target_type TEXT,
+    target_id VARCHAR,
+    ip TEXT,
+    forwarded_for TEXT,
+    user_agent TEXT,
+    success BOOLEAN NOT NULL,
+    error TEXT,
+    details JSONB,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX audit_log_created_at
+    ON audit_log (created_at);
+
+CREATE INDEX audit_log_actor_id
+    ON audit_log (actor_id);
+
+CREATE INDEX audit_log_target
+    ON audit_log (target_type, target_id);
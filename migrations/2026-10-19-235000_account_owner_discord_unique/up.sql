@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Accounts added more than once have to be deleted through the api first, unlike mappings they
+-- own jobs, group memberships and mappings so they aren't picked for deletion here
+CREATE UNIQUE INDEX controlled_account_owner_discord_idx ON controlled_account (created_by, discord_id);
@@ -0,0 +1,126 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use async_trait::async_trait;
+use axum::{Extension, Json};
+use axum::extract::{ConnectInfo, FromRequestParts, Query};
+use axum::http::header::USER_AGENT;
+use axum::http::request::Parts;
+use axum_core::response::IntoResponse;
+use log::error;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::api::ApiContext;
+use crate::api::bots::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::api::err::ApiResult;
+use crate::api::permissions::{RequirePermission, ViewAuditLog};
+use crate::db::ConnPool;
+use crate::schemas::audit_log::{AuditEntry, AuditFilter};
+
+/// Who sent the request, as far as the server can tell
+pub struct RequestMeta {
+    /// Peer address, needs the app to be served with connect info
+    pub ip: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestMeta
+    where
+        S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok()).map(String::from);
+        Ok(Self {
+            ip: parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip().to_string()),
+            forwarded_for: header("x-forwarded-for"),
+            user_agent: header(USER_AGENT.as_str()),
+        })
+    }
+}
+
+/// Writes [`AuditEntry`]s for the api and the scheduler
+#[derive(Clone)]
+pub struct AuditLog {
+    pool: ConnPool,
+}
+
+impl AuditLog {
+    pub fn new(pool: ConnPool) -> Self {
+        Self { pool }
+    }
+
+    /// Stores the entry, failing to is only logged so the action being audited still goes through
+    pub async fn record(&self, entry: AuditEntry) {
+        let mut conn = match self.pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Error getting connection: {}", e);
+                return;
+            }
+        };
+        if entry.create(&mut conn).await.is_err() {
+            error!("{} audit entry wasn't recorded", entry.action);
+        }
+    }
+
+    /// Runs `action` and records `entry` with its outcome
+    pub async fn around<T>(&self, entry: AuditEntry, action: impl Future<Output = ApiResult<T>>) -> ApiResult<T> {
+        self.around_with(entry, action, |entry, _| entry).await
+    }
+
+    /// [`AuditLog::around`] where `on_success` can add what's only known once the action went
+    /// through, like the id of what it created
+    pub async fn around_with<T>(
+        &self,
+        entry: AuditEntry,
+        action: impl Future<Output = ApiResult<T>>,
+        on_success: impl FnOnce(AuditEntry, &T) -> AuditEntry,
+    ) -> ApiResult<T> {
+        let result = action.await;
+        let entry = match &result {
+            Ok(value) => on_success(entry, value),
+            Err(_) => entry,
+        };
+        self.record(entry.result(&result)).await;
+        result
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditPageQuery {
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditLogPage {
+    items: Vec<AuditEntry>,
+    /// Entries matching the filters across every page
+    total: i64,
+}
+
+/// Page of audit entries matching the filters, newest first
+#[utoipa::path(
+    get,
+    path = "/audit-log",
+    tag = "audit-log",
+    params(AuditFilter, AuditPageQuery),
+    responses((status = 200, body = AuditLogPage)),
+)]
+pub async fn get_audit_log(
+    _: RequirePermission<ViewAuditLog>,
+    Extension(ctx): Extension<ApiContext>,
+    Query(filter): Query<AuditFilter>,
+    Query(page): Query<AuditPageQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (items, total) = AuditEntry::list_page(&filter, limit, page.offset.max(0), &mut ctx.get_conn().await?).await?;
+
+    Ok(Json(AuditLogPage { items, total }))
+}
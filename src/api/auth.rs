@@ -1,42 +1,200 @@
+use std::ops::Deref;
+use async_trait::async_trait;
 use axum::{Extension, Json};
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::{Method, StatusCode};
 use axum_core::response::IntoResponse;
 use axum_extra::extract::WithRejection;
 use serde::{Deserialize, Serialize};
-use crate::api::ApiContext;
-use crate::api::err::{ApiError, ApiResult};
+use serde_json::json;
+use utoipa::ToSchema;
+use crate::api::{ApiContext, DbConn};
+use crate::api::audit::RequestMeta;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
 use crate::api::session::WritableSession;
-use crate::auth_session;
+use crate::api::session::layer::destroy_user_sessions;
 use crate::schemas::User;
+use crate::schemas::api_token::{ApiToken, TokenScope};
+use crate::schemas::audit_log::{AuditAction, AuditEntry, AuditTarget};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct SignInPayload {
     username: String,
     password: String,
 }
 
+/// Extractor for the signed in user, or the owner of the `Authorization: Bearer` api token.
+/// Banned and kicked users are rejected and signed out everywhere. It locks the session while
+/// extracting so it has to come before a [`WritableSession`] argument
+pub struct AuthUser(pub User);
+
+impl Deref for AuthUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+    where
+        S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(ctx) = Extension::<ApiContext>::from_request_parts(parts, state).await
+            .map_err(|_| ApiError::InternalError)?;
+        if let Some(header) = parts.headers.get(AUTHORIZATION) {
+            let token = header.to_str().ok()
+                .and_then(|header| header.strip_prefix("Bearer "))
+                .ok_or(ApiError::Unauthenticated)?;
+            return token_user(token, &parts.method, &mut ctx.get_conn().await?).await.map(Self);
+        }
+        // the session is released before the handler extracts it again
+        let mut sess = WritableSession::from_request_parts(parts, state).await.unwrap_or_else(|e| match e {});
+        let uid = sess.get_user_id().ok_or(ApiError::Unauthenticated)?;
+        let mut conn = ctx.get_conn().await?;
+        let user = match User::get_by_id(uid, &mut conn).await {
+            Err(ApiError::NotFound) => {
+                sess.destroy();
+                return Err(ApiError::Unauthenticated);
+            },
+            user => user?
+        };
+        if let Err(e) = user.check_standing() {
+            sess.destroy();
+            sign_out_everywhere(&user.id, &mut conn).await?;
+            return Err(e);
+        }
+
+        Ok(Self(user))
+    }
+}
+
+/// [`AuthUser`] signed in with a session cookie, for routes managing tokens and credentials which
+/// an api token mustn't be able to reach. Same ordering rule as [`AuthUser`]
+pub struct SessionUser(pub User);
+
+impl Deref for SessionUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SessionUser
+    where
+        S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if parts.headers.contains_key(AUTHORIZATION) {
+            return Err(ApiError::Custom(
+                StatusCode::FORBIDDEN,
+                String::from("Session required"),
+                Some(String::from("Api tokens can't manage tokens or credentials, sign in instead")),
+            ));
+        }
+        let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+
+        Ok(Self(user))
+    }
+}
+
+/// Owner of the api token, `GET` and `HEAD` requests need the read scope and everything else
+/// the write scope
+async fn token_user(token: &str, method: &Method, conn: &mut DbConn) -> ApiResult<User> {
+    let mut token = match ApiToken::get_by_token(token, conn).await {
+        Err(ApiError::NotFound) => return Err(ApiError::Unauthenticated),
+        token => token?
+    };
+    if token.is_expired() {
+        return Err(ApiError::Custom(
+            StatusCode::FORBIDDEN,
+            String::from("Token expired"),
+            Some(String::from("The api token has expired")),
+        ));
+    }
+    let scope = if method == Method::GET || method == Method::HEAD { TokenScope::Read } else { TokenScope::Write };
+    if !token.has_scope(scope) {
+        return Err(ApiError::Unauthorized);
+    }
+    let user = User::get_by_id(token.user_id.clone(), conn).await?;
+    if let Err(e) = user.check_standing() {
+        sign_out_everywhere(&user.id, conn).await?;
+        return Err(e);
+    }
+    token.touch(conn).await?;
+
+    Ok(user)
+}
+
+async fn sign_out_everywhere(uid: &str, conn: &mut DbConn) -> ApiResult<()> {
+    destroy_user_sessions(uid, conn).await.map_err(map_db_err)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body = SignInPayload,
+    responses((status = 200, body = User)),
+    security(()),
+)]
 pub async fn sign_in(
     mut sess: WritableSession,
+    meta: RequestMeta,
     Extension(ctx): Extension<ApiContext>,
     WithRejection(Json(payload), _): WithRejection<Json<SignInPayload>, ApiError>
 ) -> ApiResult<Json<User>> {
-    let u = User::get_by_username(payload.username, &mut ctx.get_conn().await?).await?;
-
-    if !u.compare_passwords(payload.password) {
-        return Err(ApiError::Unauthenticated);
+    let found = User::get_by_username(payload.username.clone(), &mut ctx.get_conn().await?).await;
+    let mut entry = AuditEntry::new(AuditAction::Login, None).request(&meta).detail("username", json!(payload.username));
+    // whoever failed to sign in isn't known to be the user, so they're only the target
+    if let Ok(u) = &found {
+        entry = entry.target(AuditTarget::User, u.id.clone());
+    }
+    let result = found.and_then(|u| {
+        if !u.compare_passwords(payload.password) {
+            return Err(ApiError::Unauthenticated);
+        }
+        u.check_standing()?;
+        Ok(u)
+    });
+    if let Ok(u) = &result {
+        entry.actor_id = Some(u.id.clone());
     }
+    ctx.audit.record(entry.result(&result)).await;
+    let u = result?;
     sess.set_user(&u);
 
     Ok(Json(u))
 }
 
-pub async fn get_me(
-    sess: WritableSession,
-    Extension(ctx): Extension<ApiContext>
-) -> impl IntoResponse {
-    let uid = auth_session!(sess);
-    User::get_by_id(uid, &mut ctx.get_conn().await?).await.map(|u| Json(u))
+#[utoipa::path(
+    get,
+    path = "/me",
+    tag = "users",
+    responses((status = 200, body = User)),
+)]
+pub async fn get_me(user: AuthUser) -> Json<User> {
+    Json(user.0)
 }
 
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    responses((status = 200, description = "Empty object")),
+    security(()),
+)]
 pub async fn sign_out(
     mut sess: WritableSession,
 ) -> impl IntoResponse {
@@ -1,46 +1,584 @@
 use axum::{Extension, Json};
+use axum::extract::{Path, Query};
 use axum_core::response::IntoResponse;
 use axum_extra::extract::WithRejection;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
 use crate::api::ApiContext;
-use crate::api::session::WritableSession;
-use crate::api::err::ApiError;
-use crate::auth_session;
+use crate::api::audit::RequestMeta;
+use crate::api::auth::AuthUser;
+use crate::api::err::{ApiError, ApiResult};
+use crate::api::DbConn;
+use crate::api::permissions::{ManageAccounts, ManageGlobalDryRun, RequirePermission};
 use crate::bots::account_client::BotClient;
+use crate::bots::dry_run::IntendedAction;
+use crate::bots::manager::BotCommand;
+use crate::discord_api::errors::DiscordErrorCode;
+use crate::discord_api::gateway::IdentifyOptions;
+use crate::discord_api::ratelimit::RateLimiter;
+use crate::discord_api::snowflake::UserId;
+use crate::discord_api::token::TokenKind;
+use crate::schemas::account_mapping::AccountMapping;
+use crate::schemas::audit_log::{AuditAction, AuditEntry, AuditTarget};
 use crate::schemas::controlled_account::ControlledAccount;
+use crate::schemas::User;
 
-#[derive(Deserialize)]
+/// Page size used when the query doesn't set one
+pub(crate) const DEFAULT_PAGE_SIZE: i64 = 50;
+pub(crate) const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, ToSchema)]
 pub struct CreateBotPayload {
-    token: String
+    token: String,
+    #[serde(default)]
+    token_kind: TokenKind,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListBotsQuery {
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: i64,
+    /// Every user's accounts instead of only the caller's, staff only
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AccountPage {
+    items: Vec<ControlledAccount>,
+    /// Accounts matching the query across every page
+    total: i64,
 }
 
-#[derive(Deserialize)]
-pub struct MapBotPayload {
-    controlled_internal_id: String,
-    mapped_discord_id: String,
+#[derive(Deserialize, ToSchema)]
+pub struct PatchBotPayload {
+    label: Option<String>,
+    enabled: Option<bool>,
+    identify_options: Option<IdentifyOptions>,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct RotateTokenPayload {
+    token: String,
+    /// Defaults to the account's current kind
+    token_kind: Option<TokenKind>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MappingPayload {
+    mapped_discord_id: UserId,
+}
+
+#[utoipa::path(
+    post,
+    path = "/accounts",
+    tag = "accounts",
+    request_body = CreateBotPayload,
+    responses((status = 200, body = ControlledAccount)),
+)]
 pub async fn post_bot(
-    sess: WritableSession,
+    user: AuthUser,
+    meta: RequestMeta,
     Extension(ctx): Extension<ApiContext>,
     WithRejection(payload, _): WithRejection<Json<CreateBotPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = AuditEntry::new(AuditAction::AccountCreate, Some(user.id.clone())).request(&meta);
+    ctx.audit.around_with(entry, async {
+        let acc_client = BotClient::new(payload.token.clone(), payload.token_kind, user.id.clone()).await?;
+        let acc = acc_client.to_discord_account();
+        acc.create(&mut ctx.get_conn().await?).await?;
+        ctx.bots.new_bot(acc_client).await;
+        Ok(acc)
+    }, |entry, acc| entry.target(AuditTarget::Account, acc.id.clone())).await.map(Json)
+}
+
+/// Page of the caller's accounts, `?all=true` lists every account for staff
+#[utoipa::path(
+    get,
+    path = "/accounts",
+    tag = "accounts",
+    params(ListBotsQuery),
+    responses((status = 200, body = AccountPage)),
+)]
+pub async fn get_bots(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Query(query): Query<ListBotsQuery>,
+) -> impl IntoResponse {
+    let mut conn = ctx.get_conn().await?;
+    let owner = if query.all {
+        if !RequirePermission::<ManageAccounts>::granted(&user) {
+            return Err(ApiError::Unauthorized);
+        }
+        None
+    } else {
+        Some(user.id.clone())
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (items, total) = ControlledAccount::list_page(owner, limit, query.offset.max(0), &mut conn).await?;
+
+    Ok(Json(AccountPage { items, total }))
+}
+
+/// Account along with the result of its last token health check
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn get_bot(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
 ) -> impl IntoResponse {
-    let uid = auth_session!(sess);
-    let acc_client = BotClient::new(payload.token.clone(), uid).await?;
-    let acc = acc_client.to_discord_account();
-    acc.create(&mut ctx.get_conn().await?).await?;
+    get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await.map(Json)
+}
+
+/// Loads account, returning not found if it doesn't belong to `user` unless they can manage
+/// every account
+pub(crate) async fn get_owned_account(account_id: String, user: &User, conn: &mut DbConn) -> ApiResult<ControlledAccount> {
+    let account = ControlledAccount::get_by_id(account_id, conn).await?;
+    if account.created_by != user.id && !RequirePermission::<ManageAccounts>::granted(user) {
+        return Err(ApiError::NotFound);
+    }
+    Ok(account)
+}
+
+/// Audit entry for `action` by `user` on the account, the outcome is added once it's known
+pub(crate) fn account_entry(action: AuditAction, user: &User, account_id: &str, meta: &RequestMeta) -> AuditEntry {
+    AuditEntry::new(action, Some(user.id.clone())).target(AuditTarget::Account, account_id.to_string()).request(meta)
+}
+
+/// Updates the label, enabled flag and identify options, the bot is restarted when its
+/// connection is affected
+#[utoipa::path(
+    patch,
+    path = "/accounts/{id}",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    request_body = PatchBotPayload,
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn patch_bot(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<PatchBotPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::AccountUpdate, &user, &account_id, &meta)
+        .detail("label", json!(payload.label))
+        .detail("enabled", json!(payload.enabled))
+        .detail("identify_options", json!(payload.identify_options));
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let mut account = get_owned_account(account_id, &user, &mut conn).await?;
+        if let Some(label) = payload.label {
+            let label = label.trim().to_string();
+            account.set_label(if label.is_empty() { None } else { Some(label) }, &mut conn).await?;
+        }
+        let mut restart = false;
+        if let Some(options) = payload.identify_options {
+            restart = options != account.identify_options();
+            account.set_identify_options(&options, &mut conn).await?;
+        }
+        if let Some(enabled) = payload.enabled {
+            restart |= enabled != account.enabled;
+            account.set_enabled(enabled, &mut conn).await?;
+        }
+        if restart {
+            if account.is_runnable() {
+                ctx.bots.start(&account).await?;
+            } else {
+                ctx.bots.stop(&account.id).await;
+            }
+        }
+        Ok(account)
+    }).await.map(Json)
+}
+
+/// Swaps the account's token for a new one of the same Discord user and reconnects the bot
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/token",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    request_body = RotateTokenPayload,
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn post_bot_token(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<RotateTokenPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::AccountTokenRotate, &user, &account_id, &meta);
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let mut account = get_owned_account(account_id, &user, &mut conn).await?;
+        let token_kind = payload.token_kind.unwrap_or(account.token_kind());
+        let acc_client = BotClient::new(payload.token.clone(), token_kind, account.created_by.clone()).await?;
+        if acc_client.account_id != account.discord_id {
+            return Err(ApiError::BadRequest(String::from("Token belongs to a different Discord account")));
+        }
+        let old_token = account.token().to_string();
+        account.rotate_token(payload.token, token_kind, &mut conn).await?;
+        RateLimiter::forget_token(&old_token);
+        if account.is_runnable() {
+            ctx.bots.start(&account).await?;
+        }
+        Ok(account)
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_bot(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::AccountDelete, &user, &account_id, &meta);
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let account = get_owned_account(account_id, &user, &mut conn).await?;
+        ControlledAccount::delete_by_id(account.id.clone(), &mut conn).await?;
+        RateLimiter::forget_token(account.token());
+        ctx.bots.stop(&account.id).await;
+        ctx.bots.forget(&account.id);
+        Ok(())
+    }).await?;
+
+    Ok(Json(json!({})))
+}
+
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/enable",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn enable_bot(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::AccountEnable, &user, &account_id, &meta);
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let mut account = get_owned_account(account_id, &user, &mut conn).await?;
+        account.set_enabled(true, &mut conn).await?;
+        // accounts with an invalid token are picked up by the reconciler once a check passes
+        if account.is_runnable() {
+            ctx.bots.start(&account).await?;
+        }
+        Ok(account)
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/disable",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn disable_bot(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::AccountDisable, &user, &account_id, &meta);
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let mut account = get_owned_account(account_id, &user, &mut conn).await?;
+        account.set_enabled(false, &mut conn).await?;
+        ctx.bots.stop(&account.id).await;
+        Ok(account)
+    }).await.map(Json)
+}
 
-    Ok(Json(acc))
+/// Checks that `mapped` is another Discord user the account's token can look up
+async fn validate_mapped_user(ctx: &ApiContext, account: &ControlledAccount, mapped: UserId) -> ApiResult<()> {
+    if mapped == account.discord_id {
+        return Err(ApiError::BadRequest(String::from("Account can't be mapped to itself")));
+    }
+    let client = ctx.bots.client_for(account).await?;
+    match client.api.get_user(mapped).await {
+        Err(ApiError::Discord(details)) if details.status == 404 || details.code == DiscordErrorCode::UnknownUser => {
+            Err(ApiError::BadRequest(String::from("Unknown Discord user")))
+        },
+        Err(err) => Err(err),
+        Ok(_) => Ok(())
+    }
 }
 
-pub async fn delete_mapping() {}
+/// Loads mapping, returning not found if it isn't one of `account`'s
+async fn get_account_mapping(account: &ControlledAccount, mapping_id: String, conn: &mut DbConn) -> ApiResult<AccountMapping> {
+    let mapping = AccountMapping::get_by_id(mapping_id, conn).await?;
+    if mapping.controlled_internal_id != account.id {
+        return Err(ApiError::NotFound);
+    }
+    Ok(mapping)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/mappings",
+    tag = "mappings",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = Vec<AccountMapping>)),
+)]
+pub async fn get_mappings(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> impl IntoResponse {
+    let mut conn = ctx.get_conn().await?;
+    let account = get_owned_account(account_id, &user, &mut conn).await?;
+    AccountMapping::list_by_account(account.id, &mut conn).await.map(Json)
+}
 
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/mappings",
+    tag = "mappings",
+    params(("id" = String, Path, description = "Controlled account id")),
+    request_body = MappingPayload,
+    responses((status = 200, body = AccountMapping)),
+)]
 pub async fn map_bot(
-    sess: WritableSession
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<MappingPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::MappingCreate, &user, &account_id, &meta)
+        .detail("mapped_discord_id", json!(payload.mapped_discord_id));
+    ctx.audit.around_with(entry, async {
+        let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+        validate_mapped_user(&ctx, &account, payload.mapped_discord_id).await?;
+        let mapping = AccountMapping::new(&account, payload.mapped_discord_id);
+        mapping.create(&mut ctx.get_conn().await?).await?;
+        Ok(mapping)
+    }, |entry, mapping| entry.detail("mapping_id", json!(mapping.id))).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/mappings/{mapping_id}",
+    tag = "mappings",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("mapping_id" = String, Path, description = "Mapping id")
+    ),
+    responses((status = 200, body = AccountMapping)),
+)]
+pub async fn get_mapping(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path((account_id, mapping_id)): Path<(String, String)>,
 ) -> impl IntoResponse {
-    let uid = auth_session!(sess);
+    let mut conn = ctx.get_conn().await?;
+    let account = get_owned_account(account_id, &user, &mut conn).await?;
+    get_account_mapping(&account, mapping_id, &mut conn).await.map(Json)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/accounts/{id}/mappings/{mapping_id}",
+    tag = "mappings",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("mapping_id" = String, Path, description = "Mapping id")
+    ),
+    request_body = MappingPayload,
+    responses((status = 200, body = AccountMapping)),
+)]
+pub async fn patch_mapping(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path((account_id, mapping_id)): Path<(String, String)>,
+    WithRejection(Json(payload), _): WithRejection<Json<MappingPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::MappingUpdate, &user, &account_id, &meta)
+        .detail("mapping_id", json!(mapping_id))
+        .detail("mapped_discord_id", json!(payload.mapped_discord_id));
+    ctx.audit.around(entry, async {
+        let (account, mut mapping) = {
+            let mut conn = ctx.get_conn().await?;
+            let account = get_owned_account(account_id, &user, &mut conn).await?;
+            let mapping = get_account_mapping(&account, mapping_id, &mut conn).await?;
+            (account, mapping)
+        };
+        validate_mapped_user(&ctx, &account, payload.mapped_discord_id).await?;
+        mapping.set_mapped_discord_id(payload.mapped_discord_id, &mut ctx.get_conn().await?).await?;
+        Ok(mapping)
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}/mappings/{mapping_id}",
+    tag = "mappings",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("mapping_id" = String, Path, description = "Mapping id")
+    ),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_mapping(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path((account_id, mapping_id)): Path<(String, String)>,
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::MappingDelete, &user, &account_id, &meta)
+        .detail("mapping_id", json!(mapping_id));
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let account = get_owned_account(account_id, &user, &mut conn).await?;
+        let mapping = get_account_mapping(&account, mapping_id, &mut conn).await?;
+        AccountMapping::delete_by_id(mapping.id, &mut conn).await
+    }).await?;
+
+    Ok(Json(json!({})))
+}
+
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/commands",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Controlled account id")),
+    request_body(content = serde_json::Value, description = "`BotCommand` as `{\"type\": ..., \"data\": ...}`"),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn post_command(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    WithRejection(Json(command), _): WithRejection<Json<BotCommand>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = AuditEntry::command(user.id.clone(), account_id.clone(), &command).request(&meta);
+    ctx.audit.around(entry, async {
+        let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+        ctx.bots.send_command(&account.id, command).await
+    }).await?;
 
     Ok(Json(json!({})))
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct DryRunPayload {
+    enabled: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DryRunStatus {
+    /// Account's own flag
+    enabled: bool,
+    global: bool,
+    /// Actions recorded since the server started
+    actions: Vec<IntendedAction>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/dry-run",
+    tag = "dry-run",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = DryRunStatus)),
+)]
+pub async fn get_dry_run(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+    let actions = ctx.bots.dry_run(&account.id, account.dry_run).actions();
+
+    Ok(Json(DryRunStatus {
+        enabled: account.dry_run,
+        global: ctx.bots.global_dry_run(),
+        actions,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/accounts/{id}/dry-run",
+    tag = "dry-run",
+    params(("id" = String, Path, description = "Controlled account id")),
+    request_body = DryRunPayload,
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn put_dry_run(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<DryRunPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let entry = account_entry(AuditAction::AccountDryRun, &user, &account_id, &meta)
+        .detail("enabled", json!(payload.enabled));
+    ctx.audit.around(entry, async {
+        let mut conn = ctx.get_conn().await?;
+        let mut account = get_owned_account(account_id, &user, &mut conn).await?;
+        account.set_dry_run(payload.enabled, &mut conn).await?;
+        ctx.bots.dry_run(&account.id, account.dry_run);
+        Ok(account)
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/dry-run",
+    tag = "dry-run",
+    responses((status = 200, body = DryRunPayload)),
+)]
+pub async fn get_global_dry_run(
+    _: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+) -> impl IntoResponse {
+    Json(json!({ "enabled": ctx.bots.global_dry_run() }))
+}
+
+/// Toggles dry-run for every bot
+#[utoipa::path(
+    put,
+    path = "/dry-run",
+    tag = "dry-run",
+    request_body = DryRunPayload,
+    responses((status = 200, body = DryRunPayload)),
+)]
+pub async fn put_global_dry_run(
+    user: RequirePermission<ManageGlobalDryRun>,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<DryRunPayload>, ApiError>
+) -> impl IntoResponse {
+    ctx.bots.set_global_dry_run(payload.enabled);
+    ctx.audit.record(
+        AuditEntry::new(AuditAction::GlobalDryRun, Some(user.id.clone())).request(&meta).detail("enabled", json!(payload.enabled))
+    ).await;
+
+    Json(json!({ "enabled": ctx.bots.global_dry_run() }))
+}
@@ -0,0 +1,312 @@
+use std::time::Duration;
+use axum::{Extension, Json};
+use axum::extract::{Path, Query};
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::IntoParams;
+use crate::api::ApiContext;
+use crate::api::audit::RequestMeta;
+use crate::api::bots::{account_entry, get_owned_account};
+use crate::api::auth::AuthUser;
+use crate::api::err::{ApiError, ApiResult};
+use crate::bots::account_client::BotClient;
+use crate::bots::manager::BotCommand;
+use crate::discord_api::models::{EditMessage, ModifyCurrentUser, OutgoingMessage};
+use crate::discord_api::snowflake::{ChannelId, GuildId, MessageId, UserId};
+use crate::schemas::User;
+use crate::schemas::audit_log::{AuditAction, AuditEntry};
+use crate::schemas::controlled_account::ControlledAccount;
+
+/// Requests made for an api caller give up on long rate limits instead of hanging
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MembersQuery {
+    limit: Option<u32>,
+    after: Option<UserId>,
+}
+
+/// Client for the account, returning not found if `user` can't access it
+async fn owned_client(ctx: &ApiContext, account_id: String, user: &User) -> ApiResult<(ControlledAccount, BotClient)> {
+    let account = get_owned_account(account_id, user, &mut ctx.get_conn().await?).await?;
+    let mut client = ctx.bots.client_for(&account).await?;
+    client.api = client.api.with_max_retry_wait(MAX_RETRY_WAIT);
+    Ok((account, client))
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/user",
+    tag = "discord",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = serde_json::Value, description = "Discord's user object")),
+)]
+pub async fn get_account_user(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.get_current_user().await.map(Json)
+}
+
+/// Changes the account's username and/or avatar, returns the account with its stored username
+/// kept in sync
+#[utoipa::path(
+    patch,
+    path = "/accounts/{id}/profile",
+    tag = "discord",
+    params(("id" = String, Path, description = "Controlled account id")),
+    request_body(content = serde_json::Value, description = "Discord's modify current user body"),
+    responses((status = 200, body = ControlledAccount)),
+)]
+pub async fn patch_account_profile(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<ModifyCurrentUser>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    // the avatar is a whole image so only whether it changed is kept
+    let entry = account_entry(AuditAction::AccountUpdate, &user, &account_id, &meta)
+        .detail("username", json!(payload.username))
+        .detail("avatar", json!(payload.avatar.is_some()));
+    ctx.audit.around(entry, async {
+        let (mut account, client) = owned_client(&ctx, account_id, &user).await?;
+        if let Some(user) = client.modify_profile(payload).await? {
+            account.set_username(user.username, &mut ctx.get_conn().await?).await?;
+        }
+        Ok(account)
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/guilds",
+    tag = "discord",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = serde_json::Value, description = "Discord's partial guild objects")),
+)]
+pub async fn get_account_guilds(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.get_current_user_guilds().await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/guilds/{guild_id}",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id")
+    ),
+    responses((status = 200, body = serde_json::Value, description = "Discord's guild object")),
+)]
+pub async fn get_account_guild(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.get_guild(guild_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/guilds/{guild_id}/channels",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id")
+    ),
+    responses((status = 200, body = serde_json::Value, description = "Discord's channel objects")),
+)]
+pub async fn get_account_guild_channels(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.get_guild_channels(guild_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/guilds/{guild_id}/members",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id"),
+        MembersQuery
+    ),
+    responses((status = 200, body = serde_json::Value, description = "Discord's guild member objects")),
+)]
+pub async fn get_account_guild_members(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+    Query(query): Query<MembersQuery>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    client.api.list_guild_members(guild_id, limit, query.after).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/guilds/{guild_id}/members/{user_id}",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id"),
+        ("user_id" = UserId, Path, description = "User id")
+    ),
+    responses((status = 200, body = serde_json::Value, description = "Discord's guild member object")),
+)]
+pub async fn get_account_guild_member(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id, user_id)), _): WithRejection<Path<(String, GuildId, UserId)>, ApiError>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.get_guild_member(guild_id, user_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/channels/{channel_id}",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("channel_id" = ChannelId, Path, description = "Channel id")
+    ),
+    responses((status = 200, body = serde_json::Value, description = "Discord's channel object")),
+)]
+pub async fn get_account_channel(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, channel_id)), _): WithRejection<Path<(String, ChannelId)>, ApiError>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.get_channel(channel_id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/voice-regions",
+    tag = "discord",
+    params(("id" = String, Path, description = "Controlled account id")),
+    responses((status = 200, body = serde_json::Value, description = "Discord's voice region objects")),
+)]
+pub async fn get_voice_regions(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+) -> impl IntoResponse {
+    let (_, client) = owned_client(&ctx, account_id, &user).await?;
+
+    client.api.list_voice_regions().await.map(Json)
+}
+
+/// Posts message as the account's running bot, returns null in dry-run mode
+#[utoipa::path(
+    post,
+    path = "/accounts/{id}/channels/{channel_id}/messages",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("channel_id" = ChannelId, Path, description = "Channel id")
+    ),
+    request_body(content = serde_json::Value, description = "Discord's create message body, files as base64"),
+    responses((status = 200, body = serde_json::Value, description = "Discord's message object")),
+)]
+pub async fn post_account_message(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, channel_id)), _): WithRejection<Path<(String, ChannelId)>, ApiError>,
+    WithRejection(Json(message), _): WithRejection<Json<OutgoingMessage>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let command = BotCommand::SendMessage(channel_id, message);
+    let entry = AuditEntry::command(user.id.clone(), account_id.clone(), &command)
+        .request(&meta)
+        .detail("channel_id", json!(channel_id));
+    ctx.audit.around(entry, async {
+        let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+        ctx.bots.run_command(&account.id, command, Some(MAX_RETRY_WAIT)).await
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/accounts/{id}/channels/{channel_id}/messages/{message_id}",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("channel_id" = ChannelId, Path, description = "Channel id"),
+        ("message_id" = MessageId, Path, description = "Message id")
+    ),
+    request_body(content = serde_json::Value, description = "Discord's edit message body"),
+    responses((status = 200, body = serde_json::Value, description = "Discord's message object")),
+)]
+pub async fn patch_account_message(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, channel_id, message_id)), _): WithRejection<Path<(String, ChannelId, MessageId)>, ApiError>,
+    WithRejection(Json(edit), _): WithRejection<Json<EditMessage>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let command = BotCommand::EditMessage(channel_id, message_id, edit);
+    let entry = AuditEntry::command(user.id.clone(), account_id.clone(), &command)
+        .request(&meta)
+        .detail("channel_id", json!(channel_id))
+        .detail("message_id", json!(message_id));
+    ctx.audit.around(entry, async {
+        let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+        ctx.bots.run_command(&account.id, command, Some(MAX_RETRY_WAIT)).await
+    }).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}/channels/{channel_id}/messages/{message_id}",
+    tag = "discord",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("channel_id" = ChannelId, Path, description = "Channel id"),
+        ("message_id" = MessageId, Path, description = "Message id")
+    ),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_account_message(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, channel_id, message_id)), _): WithRejection<Path<(String, ChannelId, MessageId)>, ApiError>,
+) -> ApiResult<impl IntoResponse> {
+    let command = BotCommand::DeleteMessage(channel_id, message_id);
+    let entry = AuditEntry::command(user.id.clone(), account_id.clone(), &command)
+        .request(&meta)
+        .detail("channel_id", json!(channel_id))
+        .detail("message_id", json!(message_id));
+    ctx.audit.around(entry, async {
+        let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+        ctx.bots.run_command(&account.id, command, Some(MAX_RETRY_WAIT)).await
+    }).await?;
+
+    Ok(Json(json!({})))
+}
@@ -0,0 +1,216 @@
+use axum::{Json, Router};
+use lazy_static::lazy_static;
+use utoipa::{Modify, OpenApi};
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, ResponseBuilder};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+#[cfg(feature = "docs-ui")]
+use utoipa_swagger_ui::{Config, SwaggerUi};
+use crate::api::{audit, auth, bots, discord, events, groups, jobs, tokens, users, voice, webhooks};
+use crate::api::err::ApiErrorMessage;
+use crate::api::session::session::SESSION_COOKIE_NAME;
+use crate::bots::dry_run::IntendedAction;
+use crate::bots::manager::{CommandResult, CommandTransport};
+use crate::discord_api::errors::{DiscordErrorDetails, FieldError};
+use crate::discord_api::gateway::IdentifyOptions;
+use crate::discord_api::models::VoiceState;
+use crate::discord_api::snowflake::{ChannelId, GuildId, MessageId, Snowflake, UserId};
+use crate::discord_api::token::TokenKind;
+use crate::schemas::User;
+use crate::schemas::account_group::AccountGroup;
+use crate::schemas::account_mapping::AccountMapping;
+use crate::schemas::api_token::{ApiToken, TokenScope};
+use crate::schemas::audit_log::{AuditAction, AuditEntry, AuditTarget};
+use crate::schemas::controlled_account::ControlledAccount;
+use crate::schemas::invite::Invite;
+use crate::schemas::scheduled_job::{JobRun, ScheduledJob};
+use crate::schemas::user::Permission;
+use crate::schemas::webhook::Webhook;
+
+const ERROR_RESPONSE: &str = "Error";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "feeble-bot", description = "Dashboard api, every error is an `ApiErrorMessage`"),
+    servers((url = "/api")),
+    paths(
+        auth::sign_in,
+        auth::sign_out,
+        auth::get_me,
+        users::register,
+        users::patch_me,
+        users::put_password,
+        users::get_my_permissions,
+        users::get_users,
+        users::get_invites,
+        users::post_invite,
+        users::delete_invite,
+        tokens::get_tokens,
+        tokens::post_token,
+        tokens::delete_token,
+        bots::get_bots,
+        bots::post_bot,
+        bots::get_bot,
+        bots::patch_bot,
+        bots::delete_bot,
+        bots::post_bot_token,
+        bots::enable_bot,
+        bots::disable_bot,
+        bots::post_command,
+        bots::get_mappings,
+        bots::map_bot,
+        bots::get_mapping,
+        bots::patch_mapping,
+        bots::delete_mapping,
+        bots::get_dry_run,
+        bots::put_dry_run,
+        bots::get_global_dry_run,
+        bots::put_global_dry_run,
+        events::get_account_events,
+        events::get_events_ws,
+        discord::get_account_user,
+        discord::patch_account_profile,
+        discord::get_account_guilds,
+        discord::get_account_guild,
+        discord::get_account_guild_channels,
+        discord::get_account_guild_members,
+        discord::get_account_guild_member,
+        discord::get_account_channel,
+        discord::post_account_message,
+        discord::patch_account_message,
+        discord::delete_account_message,
+        discord::get_voice_regions,
+        voice::get_voice_state,
+        voice::put_voice_state,
+        voice::patch_voice_state,
+        voice::delete_voice_state,
+        jobs::get_jobs,
+        jobs::post_job,
+        jobs::get_job,
+        jobs::patch_job,
+        jobs::delete_job,
+        jobs::get_job_runs,
+        groups::get_groups,
+        groups::post_group,
+        groups::get_group,
+        groups::patch_group,
+        groups::delete_group,
+        groups::put_group_member,
+        groups::delete_group_member,
+        groups::post_group_command,
+        webhooks::get_webhooks,
+        webhooks::post_webhook,
+        webhooks::get_webhook,
+        webhooks::patch_webhook,
+        webhooks::delete_webhook,
+        webhooks::post_webhook_execute,
+        webhooks::patch_webhook_message,
+        webhooks::delete_webhook_message,
+        audit::get_audit_log,
+    ),
+    components(schemas(
+        ApiErrorMessage,
+        DiscordErrorDetails,
+        FieldError,
+        Snowflake,
+        GuildId,
+        ChannelId,
+        UserId,
+        MessageId,
+        auth::SignInPayload,
+        users::RegisterPayload,
+        users::PatchMePayload,
+        users::ChangePasswordPayload,
+        users::CreateInvitePayload,
+        tokens::CreateTokenPayload,
+        tokens::CreatedToken,
+        bots::CreateBotPayload,
+        bots::AccountPage,
+        bots::PatchBotPayload,
+        bots::RotateTokenPayload,
+        bots::MappingPayload,
+        bots::DryRunPayload,
+        bots::DryRunStatus,
+        voice::JoinVoicePayload,
+        voice::PatchVoicePayload,
+        jobs::CreateJobPayload,
+        jobs::UpdateJobPayload,
+        groups::GroupPayload,
+        groups::GroupWithMembers,
+        webhooks::CreateWebhookPayload,
+        webhooks::PatchWebhookPayload,
+        audit::AuditLogPage,
+        User,
+        Permission,
+        Invite,
+        ApiToken,
+        TokenScope,
+        ControlledAccount,
+        TokenKind,
+        IdentifyOptions,
+        AccountMapping,
+        IntendedAction,
+        CommandTransport,
+        CommandResult,
+        VoiceState,
+        ScheduledJob,
+        JobRun,
+        AccountGroup,
+        Webhook,
+        AuditEntry,
+        AuditAction,
+        AuditTarget,
+    )),
+    modifiers(&Security, &ErrorResponses),
+    security(("session" = []), ("api_token" = [])),
+)]
+pub struct ApiDoc;
+
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        // cookie set by `PgSessionLayer`
+        components.add_security_scheme("session", SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE_NAME))));
+        // personal token, see `ApiToken`
+        components.add_security_scheme("api_token", SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+    }
+}
+
+/// Every operation can fail with an `ApiErrorMessage`, added here instead of on every path
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let error = ResponseBuilder::new()
+            .description("Error, the status depends on what went wrong")
+            .content("application/json", ContentBuilder::new().schema(Ref::from_schema_name("ApiErrorMessage")).build())
+            .build();
+        openapi.components.get_or_insert_with(Default::default).responses.insert(ERROR_RESPONSE.to_string(), RefOr::T(error));
+        for path in openapi.paths.paths.values_mut() {
+            for operation in path.operations.values_mut() {
+                operation.responses.responses.insert(
+                    String::from("default"),
+                    RefOr::Ref(Ref::from_response_name(ERROR_RESPONSE)),
+                );
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref OPENAPI: utoipa::openapi::OpenApi = ApiDoc::openapi();
+}
+
+pub async fn get_openapi() -> Json<&'static utoipa::openapi::OpenApi> {
+    Json(&OPENAPI)
+}
+
+/// Swagger UI for the spec at `/docs`, only served by dev builds with the `docs-ui` feature
+pub fn docs_ui() -> Router {
+    #[cfg(feature = "docs-ui")]
+    if !crate::PROD {
+        return SwaggerUi::new("/docs").config(Config::from("/api/openapi.json")).into();
+    }
+    Router::new()
+}
@@ -1,24 +1,48 @@
-use axum::extract::rejection::JsonRejection;
+use axum::extract::rejection::{JsonRejection, PathRejection};
 use axum::http::StatusCode;
 use axum::Json;
 use axum_core::response::{IntoResponse, Response};
+use diesel_async::pooled_connection::deadpool::PoolError;
+use log::error;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use log::error;
+use utoipa::ToSchema;
+use crate::discord_api::errors::DiscordErrorDetails;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct ApiErrorMessage {
     error_msg: String,
     user_msg: String,
+    /// Set when the error comes from a request to Discord
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discord: Option<DiscordErrorDetails>,
 }
 
 fn error_msg(err: &str, usr_msg: Option<&str>) -> Json<ApiErrorMessage> {
     Json(ApiErrorMessage {
         error_msg: err.to_string(),
         user_msg: usr_msg.unwrap_or("Something went wrong").to_string(),
+        discord: None,
     })
 }
 
+/// Status we respond with when Discord rejected a request, auth problems with the account's
+/// token aren't the api caller's fault
+fn discord_error_response(details: DiscordErrorDetails) -> (StatusCode, Json<ApiErrorMessage>) {
+    let (stat, usr_msg) = match details.status {
+        400 => (StatusCode::BAD_REQUEST, "Discord rejected the request"),
+        401 => (StatusCode::BAD_GATEWAY, "Discord rejected the account's token"),
+        403 => (StatusCode::FORBIDDEN, "The account is missing permissions for this action"),
+        404 => (StatusCode::NOT_FOUND, "Resource not found"),
+        _ => (StatusCode::BAD_GATEWAY, "Something went wrong"),
+    };
+    (stat, Json(ApiErrorMessage {
+        error_msg: details.message.clone(),
+        user_msg: usr_msg.to_string(),
+        discord: Some(details),
+    }))
+}
+
 #[macro_export]
 macro_rules! conv_search_err {
     (
@@ -34,6 +58,19 @@ macro_rules! conv_search_err {
     };
 }
 
+/// Logs a database error and hides it from the caller, for queries where not found isn't expected
+/// either, see [`conv_search_err`] for lookups
+pub fn map_db_err(e: diesel::result::Error) -> ApiError {
+    error!("{e}");
+    ApiError::InternalError
+}
+
+/// Logs a failure to check a connection out of the pool and hides it from the caller
+pub fn map_pool_err(e: PoolError) -> ApiError {
+    error!("Error getting connection: {e}");
+    ApiError::InternalError
+}
+
 pub type ApiResult<T> = Result<T, ApiError>;
 #[derive(Debug, Error)]
 pub enum ApiError {
@@ -47,10 +84,22 @@ pub enum ApiError {
     InternalError,
     #[error("{1}")]
     Custom(StatusCode, String, Option<String>),
-    #[error("")]
+    #[error("{0}")]
     BadRequest(String),
     #[error(transparent)]
     JsonDecodeError(#[from] JsonRejection),
+    /// Path parameter that doesn't parse, like a malformed snowflake
+    #[error(transparent)]
+    PathDecodeError(#[from] PathRejection),
+    #[error("{}", .0.message)]
+    Discord(DiscordErrorDetails),
+}
+
+/// Lets transactions, which need their error to come from diesel's, return [`ApiError`]s
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        conv_search_err!(e)
+    }
 }
 
 impl IntoResponse for ApiError {
@@ -81,6 +130,11 @@ impl IntoResponse for ApiError {
                 StatusCode::BAD_REQUEST,
                 error_msg(e.to_string().as_str(), None),
             ),
+            ApiError::PathDecodeError(e) => (
+                StatusCode::BAD_REQUEST,
+                error_msg(e.to_string().as_str(), None),
+            ),
+            ApiError::Discord(details) => discord_error_response(details),
         }
             .into_response()
     }
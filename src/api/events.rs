@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::ready;
+use std::sync::Arc;
+use axum::Extension;
+use axum::extract::{Path, Query};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum_core::response::IntoResponse;
+use futures_util::{Stream, StreamExt, stream};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use crate::api::ApiContext;
+use crate::api::bots::get_owned_account;
+use crate::api::auth::AuthUser;
+use crate::api::err::ApiError;
+use crate::bots::events::{BotEvent, EventFilter, EventHub};
+use crate::discord_api::snowflake::GuildId;
+use crate::schemas::User;
+
+/// Events queued for a socket before a slow client starts missing some
+const SOCKET_QUEUE_SIZE: usize = 256;
+
+/// Replayed events followed by live ones, both filtered. Subscribers that fall too far behind
+/// skip the events they missed
+fn event_stream(hub: Arc<EventHub>, filter: EventFilter) -> impl Stream<Item = BotEvent> {
+    let (recent, receiver) = hub.subscribe();
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(missed)) => warn!("event subscriber lagged, skipped {missed} events"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    stream::iter(recent).chain(live).filter(move |event| ready(filter.matches(event)))
+}
+
+/// Server-Sent Events stream of the account's events, see [`EventFilter`] for the query
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/events",
+    tag = "events",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        EventFilter
+    ),
+    responses((status = 200, content_type = "text/event-stream", description = "`BotEvent`s named after their type")),
+)]
+pub async fn get_account_events(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(account_id): Path<String>,
+    Query(filter): Query<EventFilter>,
+) -> impl IntoResponse {
+    let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+    let events = event_stream(ctx.bots.events(&account.id), filter).map(|event| {
+        Ok::<_, Infallible>(Event::default().event(event.name()).json_data(&event).unwrap_or_default())
+    });
+
+    Ok::<_, ApiError>(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Sent by the dashboard over the events socket
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Replaces the account's existing subscription
+    Subscribe {
+        account_id: String,
+        types: Option<String>,
+        guild_id: Option<GuildId>,
+    },
+    Unsubscribe {
+        account_id: String,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ServerMessage {
+    Subscribed { account_id: String },
+    Unsubscribed { account_id: String },
+    Event { event: BotEvent },
+    /// The client read too slowly and `missed` of the account's events were dropped
+    Lagged { account_id: String, missed: u64 },
+    Error { message: String },
+}
+
+/// WebSocket carrying the events of every account the caller subscribes to
+#[utoipa::path(
+    get,
+    path = "/events/ws",
+    tag = "events",
+    responses((status = 101, description = "WebSocket, subscribe to accounts with `{\"op\": \"subscribe\", \"account_id\": ...}`")),
+)]
+pub async fn get_events_ws(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| events_socket(socket, ctx, user.0))
+}
+
+/// Writes `message` to the socket, false once the socket is gone
+async fn send_message(socket: &mut WebSocket, message: &ServerMessage) -> bool {
+    match serde_json::to_string(message) {
+        Err(e) => {
+            warn!("{e}");
+            true
+        },
+        Ok(text) => socket.send(Message::Text(text)).await.is_ok()
+    }
+}
+
+async fn events_socket(mut socket: WebSocket, ctx: ApiContext, user: User) {
+    // only events are queued, replies are written directly so they're never dropped
+    let (out_s, mut out_r) = channel::<ServerMessage>(SOCKET_QUEUE_SIZE);
+    let mut subscriptions = HashMap::<String, JoinHandle<()>>::new();
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = on_client_message(&text, &ctx, &user, &out_s, &mut subscriptions).await
+                    .unwrap_or_else(|message| ServerMessage::Error { message });
+                if !send_message(&mut socket, &reply).await {
+                    break;
+                }
+            },
+            Some(outgoing) = out_r.recv() => {
+                if !send_message(&mut socket, &outgoing).await {
+                    break;
+                }
+            },
+        }
+    }
+    for task in subscriptions.into_values() {
+        task.abort();
+    }
+}
+
+async fn on_client_message(
+    text: &str,
+    ctx: &ApiContext,
+    user: &User,
+    out: &Sender<ServerMessage>,
+    subscriptions: &mut HashMap<String, JoinHandle<()>>,
+) -> Result<ServerMessage, String> {
+    match serde_json::from_str::<ClientMessage>(text).map_err(|e| e.to_string())? {
+        ClientMessage::Subscribe { account_id, types, guild_id } => {
+            let mut conn = ctx.get_conn().await.map_err(|e| e.to_string())?;
+            let account = get_owned_account(account_id, user, &mut conn).await.map_err(|e| e.to_string())?;
+            if let Some(task) = subscriptions.remove(&account.id) {
+                task.abort();
+            }
+            let mut events = Box::pin(event_stream(ctx.bots.events(&account.id), EventFilter { types, guild_id }));
+            let out = out.clone();
+            let task_account_id = account.id.clone();
+            subscriptions.insert(account.id.clone(), tokio::spawn(async move {
+                // like the broadcast channel, a full queue drops events rather than buffering
+                // without bound, the client is told how many it missed once there's room again
+                let mut missed = 0u64;
+                while let Some(event) = events.next().await {
+                    if missed > 0 {
+                        match out.try_send(ServerMessage::Lagged { account_id: task_account_id.clone(), missed }) {
+                            Ok(()) => missed = 0,
+                            Err(TrySendError::Full(_)) => {
+                                missed += 1;
+                                continue;
+                            },
+                            Err(TrySendError::Closed(_)) => return,
+                        }
+                    }
+                    match out.try_send(ServerMessage::Event { event }) {
+                        Ok(()) => {},
+                        Err(TrySendError::Full(_)) => {
+                            if missed == 0 {
+                                warn!("events socket lagged, dropping events of {task_account_id}");
+                            }
+                            missed += 1;
+                        },
+                        Err(TrySendError::Closed(_)) => return,
+                    }
+                }
+            }));
+            Ok(ServerMessage::Subscribed { account_id: account.id })
+        },
+        ClientMessage::Unsubscribe { account_id } => {
+            if let Some(task) = subscriptions.remove(&account_id) {
+                task.abort();
+            }
+            Ok(ServerMessage::Unsubscribed { account_id })
+        },
+    }
+}
@@ -0,0 +1,218 @@
+use axum::{Extension, Json};
+use axum::extract::Path;
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+use crate::api::ApiContext;
+use crate::api::audit::RequestMeta;
+use crate::api::auth::AuthUser;
+use crate::api::bots::get_owned_account;
+use crate::api::err::{ApiError, ApiResult};
+use crate::api::DbConn;
+use crate::api::permissions::{ManageAccounts, RequirePermission};
+use crate::bots::manager::BotCommand;
+use crate::schemas::account_group::AccountGroup;
+use crate::schemas::audit_log::AuditEntry;
+use crate::schemas::User;
+
+#[derive(Deserialize, ToSchema)]
+pub struct GroupPayload {
+    name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupWithMembers {
+    #[serde(flatten)]
+    group: AccountGroup,
+    members: Vec<String>,
+}
+
+/// Loads group, returning not found if it doesn't belong to `user` unless they can manage every
+/// account, like jobs groups only exist to act on accounts
+async fn get_owned_group(group_id: String, user: &User, conn: &mut DbConn) -> ApiResult<AccountGroup> {
+    let group = AccountGroup::get_by_id(group_id, conn).await?;
+    if group.created_by != user.id && !RequirePermission::<ManageAccounts>::granted(user) {
+        return Err(ApiError::NotFound);
+    }
+    Ok(group)
+}
+
+fn validate_name(name: &str) -> ApiResult<()> {
+    if name.trim().is_empty() {
+        return Err(ApiError::BadRequest(String::from("Group name can't be empty")));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/groups",
+    tag = "groups",
+    responses((status = 200, body = Vec<AccountGroup>)),
+)]
+pub async fn get_groups(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+) -> impl IntoResponse {
+    AccountGroup::list_by_creator(user.id.clone(), &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/groups",
+    tag = "groups",
+    request_body = GroupPayload,
+    responses((status = 200, body = AccountGroup)),
+)]
+pub async fn post_group(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<GroupPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    validate_name(&payload.name)?;
+    let group = AccountGroup::new(payload.name, user.id.clone());
+    group.create(&mut ctx.get_conn().await?).await?;
+
+    Ok(Json(group))
+}
+
+#[utoipa::path(
+    get,
+    path = "/groups/{id}",
+    tag = "groups",
+    params(("id" = String, Path, description = "Group id")),
+    responses((status = 200, body = GroupWithMembers)),
+)]
+pub async fn get_group(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(group_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let group = get_owned_group(group_id, &user, &mut conn).await?;
+    let members = group.member_ids(&mut conn).await?;
+
+    Ok(Json(GroupWithMembers { group, members }))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/groups/{id}",
+    tag = "groups",
+    params(("id" = String, Path, description = "Group id")),
+    request_body = GroupPayload,
+    responses((status = 200, body = AccountGroup)),
+)]
+pub async fn patch_group(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(group_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<GroupPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    validate_name(&payload.name)?;
+    let mut conn = ctx.get_conn().await?;
+    let mut group = get_owned_group(group_id, &user, &mut conn).await?;
+    group.rename(payload.name, &mut conn).await?;
+
+    Ok(Json(group))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/groups/{id}",
+    tag = "groups",
+    params(("id" = String, Path, description = "Group id")),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_group(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(group_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let group = get_owned_group(group_id, &user, &mut conn).await?;
+    AccountGroup::delete_by_id(group.id, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
+
+#[utoipa::path(
+    put,
+    path = "/groups/{id}/members/{account_id}",
+    tag = "groups",
+    params(
+        ("id" = String, Path, description = "Group id"),
+        ("account_id" = String, Path, description = "Controlled account id")
+    ),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn put_group_member(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path((group_id, account_id)): Path<(String, String)>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let group = get_owned_group(group_id, &user, &mut conn).await?;
+    let account = get_owned_account(account_id, &user, &mut conn).await?;
+    group.add_member(account.id, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/groups/{id}/members/{account_id}",
+    tag = "groups",
+    params(
+        ("id" = String, Path, description = "Group id"),
+        ("account_id" = String, Path, description = "Controlled account id")
+    ),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_group_member(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path((group_id, account_id)): Path<(String, String)>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let group = get_owned_group(group_id, &user, &mut conn).await?;
+    group.remove_member(account_id, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
+
+/// Sends command to every member of the group, returns a result per account
+#[utoipa::path(
+    post,
+    path = "/groups/{id}/commands",
+    tag = "groups",
+    params(("id" = String, Path, description = "Group id")),
+    request_body(content = serde_json::Value, description = "`BotCommand` as `{\"type\": ..., \"data\": ...}`"),
+    responses((status = 200, body = Vec<CommandResult>)),
+)]
+pub async fn post_group_command(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    Path(group_id): Path<String>,
+    WithRejection(Json(command), _): WithRejection<Json<BotCommand>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let members = {
+        let mut conn = ctx.get_conn().await?;
+        let group = get_owned_group(group_id.clone(), &user, &mut conn).await?;
+        group.member_ids(&mut conn).await?
+    };
+    let results = ctx.bots.send_group_command(members, command.clone()).await;
+    for result in &results {
+        ctx.audit.record(
+            AuditEntry::command(user.id.clone(), result.account_id.clone(), &command)
+                .request(&meta)
+                .detail("group_id", json!(group_id))
+                .outcome(result.success, result.error.clone())
+        ).await;
+    }
+
+    Ok(Json(results))
+}
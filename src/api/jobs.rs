@@ -0,0 +1,153 @@
+use axum::{Extension, Json};
+use axum::extract::Path;
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+use crate::api::ApiContext;
+use crate::api::auth::AuthUser;
+use crate::api::bots::get_owned_account;
+use crate::api::err::{ApiError, ApiResult};
+use crate::api::DbConn;
+use crate::api::permissions::{ManageAccounts, RequirePermission};
+use crate::bots::manager::BotCommand;
+use crate::schemas::scheduled_job::{JobRun, ScheduledJob};
+use crate::schemas::User;
+
+/// Number of runs returned by the history endpoint
+const RUN_HISTORY_LIMIT: i64 = 100;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateJobPayload {
+    account_id: String,
+    /// See `BotCommand`
+    #[schema(value_type = Object)]
+    command: BotCommand,
+    run_at: Option<DateTime<Utc>>,
+    cron: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateJobPayload {
+    enabled: bool,
+}
+
+/// Loads job, returning not found if it doesn't belong to `user` unless they can manage every
+/// account, jobs only act on accounts so staff get the same reach as in [`get_owned_account`]
+async fn get_owned_job(job_id: String, user: &User, conn: &mut DbConn) -> ApiResult<ScheduledJob> {
+    let job = ScheduledJob::get_by_id(job_id, conn).await?;
+    if job.created_by != user.id && !RequirePermission::<ManageAccounts>::granted(user) {
+        return Err(ApiError::NotFound);
+    }
+    Ok(job)
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    tag = "jobs",
+    responses((status = 200, body = Vec<ScheduledJob>)),
+)]
+pub async fn get_jobs(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+) -> impl IntoResponse {
+    ScheduledJob::list_by_creator(user.id.clone(), &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    tag = "jobs",
+    request_body = CreateJobPayload,
+    responses((status = 200, body = ScheduledJob)),
+)]
+pub async fn post_job(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateJobPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let account = get_owned_account(payload.account_id, &user, &mut conn).await?;
+    let job = ScheduledJob::new(account.id, &payload.command, payload.run_at, payload.cron, user.id.clone())?;
+    job.create(&mut conn).await?;
+
+    Ok(Json(job))
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job id")),
+    responses((status = 200, body = ScheduledJob)),
+)]
+pub async fn get_job(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    get_owned_job(job_id, &user, &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job id")),
+    request_body = UpdateJobPayload,
+    responses((status = 200, body = ScheduledJob)),
+)]
+pub async fn patch_job(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(job_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<UpdateJobPayload>, ApiError>
+) -> impl IntoResponse {
+    let mut conn = ctx.get_conn().await?;
+    let mut job = get_owned_job(job_id, &user, &mut conn).await?;
+    if payload.enabled && job.next_run.is_none() && job.cron.is_none() {
+        return Err(ApiError::BadRequest(String::from("One-shot job has already run")));
+    }
+    job.set_enabled(payload.enabled, &mut conn).await?;
+
+    Ok(Json(job))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job id")),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_job(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(job_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let job = get_owned_job(job_id, &user, &mut conn).await?;
+    ScheduledJob::delete_by_id(job.id, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/runs",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job id")),
+    responses((status = 200, body = Vec<JobRun>)),
+)]
+pub async fn get_job_runs(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    let mut conn = ctx.get_conn().await?;
+    let job = get_owned_job(job_id, &user, &mut conn).await?;
+    JobRun::list_for_job(job.id, RUN_HISTORY_LIMIT, &mut conn).await.map(Json)
+}
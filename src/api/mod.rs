@@ -2,66 +2,126 @@ pub(crate) mod session;
 pub mod auth;
 pub mod err;
 mod bots;
+mod jobs;
+mod groups;
+mod discord;
+mod webhooks;
+mod events;
+mod voice;
+mod users;
+mod tokens;
+mod docs;
+pub mod audit;
+pub mod permissions;
 
 use std::env::var;
 use std::sync::Arc;
 use axum::Router;
-use axum::routing::{post, get};
+use axum::extract::DefaultBodyLimit;
+use axum::routing::{delete, get, patch, post, put};
 use diesel_async::AsyncPgConnection;
 use diesel_async::pooled_connection::deadpool::Object;
-use log::error;
 use tower_http::add_extension::AddExtensionLayer;
 use tower::ServiceBuilder;
-use crate::api::auth::sign_in;
-use crate::api::err::ApiError;
+use crate::api::audit::{get_audit_log, AuditLog};
+use crate::api::auth::{sign_in, sign_out};
+use crate::api::docs::{docs_ui, get_openapi};
+use crate::api::discord::{delete_account_message, get_account_channel, get_account_guild, get_account_guild_channels, get_account_guild_member, get_account_guild_members, get_account_guilds, get_account_user, get_voice_regions, patch_account_message, patch_account_profile, post_account_message};
+use crate::api::bots::{delete_bot, delete_mapping, disable_bot, enable_bot, get_bot, get_bots, get_dry_run, get_global_dry_run, get_mapping, get_mappings, map_bot, patch_bot, patch_mapping, post_bot, post_bot_token, post_command, put_dry_run, put_global_dry_run};
+use crate::api::err::{map_pool_err, ApiError};
+use crate::api::events::{get_account_events, get_events_ws};
+use crate::api::groups::{delete_group, delete_group_member, get_group, get_groups, patch_group, post_group, post_group_command, put_group_member};
+use crate::api::jobs::{delete_job, get_job, get_job_runs, get_jobs, patch_job, post_job};
 use crate::api::session::layer::PgSessionLayer;
+use crate::api::tokens::{delete_token, get_tokens, post_token};
+use crate::api::users::{delete_invite, get_invites, get_my_permissions, get_users, patch_me, post_invite, put_password, register};
+use crate::api::voice::{delete_voice_state, get_voice_state, patch_voice_state, put_voice_state};
+use crate::api::webhooks::{delete_webhook, delete_webhook_message, get_webhook, get_webhooks, patch_webhook, patch_webhook_message, post_webhook, post_webhook_execute};
+use crate::bots::manager::BotManager;
 use crate::db::ConnPool;
 use crate::PROD;
 
 use self::auth::get_me;
 
-/// authenticates session
-#[macro_export]
-macro_rules! auth_session {
-    (
-        $sess:expr
-    ) => {
-        match $sess.get_user_id() {
-            None => return Err(ApiError::Unauthenticated),
-            Some(uid) => uid,
-        }
-    };
-}
-
 pub type DbConn = Object<AsyncPgConnection>;
 #[derive(Clone)]
 pub struct ApiContext {
     pub db: Arc<ConnPool>,
+    pub bots: Arc<BotManager>,
+    pub audit: AuditLog,
 }
 
 impl ApiContext {
     pub async fn get_conn(&self) -> Result<Object<AsyncPgConnection>, ApiError> {
-        match self.db.get().await {
-            Ok(conn) => Ok(conn),
-            Err(e) => {
-                error!("Error getting connection: {}", e);
-                Err(ApiError::InternalError)
-            }
-        }
+        self.db.get().await.map_err(map_pool_err)
     }
 }
 
 
-pub fn get_router(p: ConnPool) -> anyhow::Result<Router> {
+pub fn get_router(p: ConnPool, bots: Arc<BotManager>) -> anyhow::Result<Router> {
+    let audit = AuditLog::new(p.clone());
     let conn_pool = Arc::new(p);
     let session_layer = PgSessionLayer::new(
         hex::decode(var("COOKIE_SECRET").expect("COOKIE_SECRET isn't valid"))?.as_slice(),
         PROD,
         conn_pool.clone(),
     );
-    Ok(Router::new().route("/login", post(sign_in)).route("/me", get(get_me)).layer(session_layer).layer(ServiceBuilder::new().layer(AddExtensionLayer::new(
+    Ok(Router::new()
+        .route("/openapi.json", get(get_openapi))
+        .merge(docs_ui())
+        .route("/login", post(sign_in))
+        .route("/logout", post(sign_out))
+        .route("/register", post(register))
+        .route("/me", get(get_me).patch(patch_me))
+        .route("/me/password", put(put_password))
+        .route("/me/permissions", get(get_my_permissions))
+        .route("/me/tokens", get(get_tokens).post(post_token))
+        .route("/me/tokens/:id", delete(delete_token))
+        .route("/users", get(get_users))
+        .route("/invites", get(get_invites).post(post_invite))
+        .route("/invites/:code", delete(delete_invite))
+        .route("/accounts", get(get_bots).post(post_bot))
+        .route("/accounts/:id", get(get_bot).patch(patch_bot).delete(delete_bot))
+        .route("/accounts/:id/token", post(post_bot_token))
+        .route("/accounts/:id/mappings", get(get_mappings).post(map_bot))
+        .route("/accounts/:id/events", get(get_account_events))
+        .route("/events/ws", get(get_events_ws))
+        .route("/accounts/:id/mappings/:mapping_id", get(get_mapping).patch(patch_mapping).delete(delete_mapping))
+        .route("/accounts/:id/enable", post(enable_bot))
+        .route("/accounts/:id/disable", post(disable_bot))
+        .route("/accounts/:id/commands", post(post_command))
+        .route("/accounts/:id/dry-run", get(get_dry_run).put(put_dry_run))
+        .route("/accounts/:id/user", get(get_account_user))
+        .route("/accounts/:id/profile", patch(patch_account_profile))
+        .route("/accounts/:id/guilds", get(get_account_guilds))
+        .route("/accounts/:id/guilds/:guild_id", get(get_account_guild))
+        .route("/accounts/:id/guilds/:guild_id/channels", get(get_account_guild_channels))
+        .route("/accounts/:id/guilds/:guild_id/members", get(get_account_guild_members))
+        .route("/accounts/:id/guilds/:guild_id/members/:user_id", get(get_account_guild_member))
+        .route("/accounts/:id/guilds/:guild_id/voice", get(get_voice_state).put(put_voice_state).patch(patch_voice_state).delete(delete_voice_state))
+        .route("/accounts/:id/channels/:channel_id", get(get_account_channel))
+        // files are sent base64 encoded in the json body, leave room for Discord's 25MB upload limit
+        .route("/accounts/:id/channels/:channel_id/messages", post(post_account_message).layer(DefaultBodyLimit::max(35 * 1024 * 1024)))
+        .route("/accounts/:id/channels/:channel_id/messages/:message_id", patch(patch_account_message).delete(delete_account_message))
+        .route("/accounts/:id/voice-regions", get(get_voice_regions))
+        .route("/dry-run", get(get_global_dry_run).put(put_global_dry_run))
+        .route("/jobs", get(get_jobs).post(post_job))
+        .route("/jobs/:id", get(get_job).patch(patch_job).delete(delete_job))
+        .route("/jobs/:id/runs", get(get_job_runs))
+        .route("/groups", get(get_groups).post(post_group))
+        .route("/groups/:id", get(get_group).patch(patch_group).delete(delete_group))
+        .route("/groups/:id/members/:account_id", put(put_group_member).delete(delete_group_member))
+        .route("/groups/:id/commands", post(post_group_command))
+        .route("/webhooks", get(get_webhooks).post(post_webhook))
+        .route("/webhooks/:id", get(get_webhook).patch(patch_webhook).delete(delete_webhook))
+        .route("/webhooks/:id/execute", post(post_webhook_execute))
+        .route("/webhooks/:id/messages/:message_id", patch(patch_webhook_message).delete(delete_webhook_message))
+        .route("/audit-log", get(get_audit_log))
+        .layer(session_layer).layer(ServiceBuilder::new().layer(AddExtensionLayer::new(
         ApiContext {
             db: conn_pool,
+            bots,
+            audit,
         }
     ))))
 }
@@ -0,0 +1,69 @@
+use std::marker::PhantomData;
+use std::ops::Deref;
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use crate::api::auth::AuthUser;
+use crate::api::err::ApiError;
+use crate::schemas::User;
+use crate::schemas::user::Permission;
+
+/// Type level [`Permission`] for [`RequirePermission`]
+pub trait PermissionGuard: Send + Sync + 'static {
+    const PERMISSION: Permission;
+}
+
+macro_rules! permission_guard {
+    ($($name:ident),* $(,)?) => {
+        $(
+            pub struct $name;
+
+            impl PermissionGuard for $name {
+                const PERMISSION: Permission = Permission::$name;
+            }
+        )*
+    };
+}
+
+permission_guard!(ManageAccounts, ManageInvites, ManageUsers, ManageGlobalDryRun, ViewAuditLog);
+
+/// Extractor rejecting the request unless the signed in user has `P`, derefs to that user. Like
+/// [`AuthUser`] it has to come before a `WritableSession` argument
+pub struct RequirePermission<P: PermissionGuard> {
+    pub user: User,
+    permission: PhantomData<P>,
+}
+
+impl<P: PermissionGuard> RequirePermission<P> {
+    /// Whether `user` has `P`, for handlers where the permission only widens what the caller can
+    /// reach, e.g. staff managing accounts they didn't create
+    pub fn granted(user: &User) -> bool {
+        user.has_permission(P::PERMISSION)
+    }
+}
+
+impl<P: PermissionGuard> Deref for RequirePermission<P> {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for RequirePermission<P>
+    where
+        S: Send + Sync,
+        P: PermissionGuard,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+        if !Self::granted(&user) {
+            return Err(ApiError::Unauthorized);
+        }
+
+        Ok(Self { user, permission: PhantomData })
+    }
+}
@@ -1,4 +1,3 @@
-use std::borrow::Cow;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use std::collections::HashMap;
@@ -17,7 +16,7 @@ use axum_extra::extract::CookieJar;
 
 use cookie::Cookie;
 use futures_util::future::BoxFuture;
-use log::{debug, error, info};
+use log::{error, info};
 use tokio::sync::OwnedRwLockWriteGuard;
 use tokio::sync::RwLock;
 use tower::Layer;
@@ -31,7 +30,7 @@ use base64::prelude::BASE64_STANDARD;
 use diesel::{QueryResult, SelectableHelper};
 use crate::api::DbConn;
 use crate::schema::sessions::dsl::sessions;
-use crate::schema::sessions::{sess_cookie, sess_id};
+use crate::schema::sessions::{sess_cookie, sess_id, uid};
 use crate::api::session::session::{DBSession, Session, SessionHandle, SESSION_COOKIE_NAME};
 use crate::db::ConnPool;
 
@@ -130,7 +129,7 @@ impl PgSessionLayer {
         mac.update(cookie.value().as_bytes());
 
         // Cookie's new value is [MAC | original-value].
-        let mut new_value = base64::encode(mac.finalize().into_bytes());
+        let mut new_value = BASE64_STANDARD.encode(mac.finalize().into_bytes());
         new_value.push_str(cookie.value());
         cookie.set_value(new_value);
     }
@@ -214,11 +213,11 @@ impl<S> Service<Request<Body>> for PgSessionMiddleware<S>
             let mut cook = cookies.get(this.layer.session_cookie);
 
             // if invalid cookie passed, set cook to None
-            if let Err(_) = this.layer.verify_signature(
+            if this.layer.verify_signature(
                 decode(cook.unwrap_or(&String::new()).as_str())
-                    .unwrap_or(Cow::default())
+                    .unwrap_or_default()
                     .deref(),
-            ) {
+            ).is_err() {
                 cook = None;
             }
 
@@ -255,7 +254,7 @@ impl<S> Service<Request<Body>> for PgSessionMiddleware<S>
             drop(db_conn);
 
             let mut sid_cook = Cookie::build((SESSION_COOKIE_NAME, session.cookie))
-                .secure(this.layer.secure.clone())
+                .secure(this.layer.secure)
                 .http_only(true)
                 .path("/")
                 .build();
@@ -272,6 +271,11 @@ impl<S> Service<Request<Body>> for PgSessionMiddleware<S>
     }
 }
 
+/// Remove every session signed in as `user_id` from the database
+pub async fn destroy_user_sessions(user_id: &str, conn: &mut DbConn) -> QueryResult<usize> {
+    diesel::delete(sessions.filter(uid.eq(user_id))).execute(conn).await
+}
+
 // An extractor which provides a writable session. Sessions may have only one
 // writer.
 #[derive(Debug)]
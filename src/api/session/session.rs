@@ -21,7 +21,7 @@ lazy_static! {
     pub static ref SESS_TIMEOUT: Duration = Duration::try_days(5).unwrap();
 }
 
-pub static SESSION_COOKIE_NAME: &'static str = "sid";
+pub static SESSION_COOKIE_NAME: &str = "sid";
 
 #[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable)]
 #[diesel(table_name = sessions)]
@@ -40,7 +40,7 @@ impl Default for DBSession {
         DBSession {
             sess_id: id.clone(),
             sess_cookie: Session::generate_cookie(id),
-            expiry: Utc::now().add(SESS_TIMEOUT.clone()).naive_utc(),
+            expiry: Utc::now().add(*SESS_TIMEOUT).naive_utc(),
             uid: None,
             data: Some(Value::Object(serde_json::Map::new())),
         }
@@ -53,7 +53,7 @@ impl DBSession {
         Session {
             id: id.clone(),
             expiry: self.expiry.and_utc(),
-            data: self.data_to_hashmap().unwrap_or(HashMap::new()),
+            data: self.data_to_hashmap().unwrap_or_default(),
             cookie: Session::generate_cookie(id),
             user_id: self.uid.clone(),
             ..Default::default()
@@ -63,8 +63,8 @@ impl DBSession {
     pub fn data_to_hashmap(&self) -> anyhow::Result<HashMap<String, String>> {
         let mut out: HashMap<String, String> = HashMap::new();
 
-        match self.data.as_ref() {
-            Some(s) => match s {
+        if let Some(s) = self.data.as_ref() {
+            match s {
                 Value::Object(_o) => {
                     return Ok(serde_json::from_value(s.to_owned())?);
                 }
@@ -72,8 +72,7 @@ impl DBSession {
                     let data_val = serde_json::to_string(s).unwrap_or(String::new());
                     out.insert(String::from("data"), strip_quotes(data_val));
                 }
-            },
-            None => {}
+            }
         }
         Ok(out)
     }
@@ -95,7 +94,7 @@ impl Default for Session {
         let id = hex::encode(random::<[u8; 32]>());
         Session {
             id: id.clone(),
-            expiry: Utc::now().add(SESS_TIMEOUT.clone()),
+            expiry: Utc::now().add(*SESS_TIMEOUT),
             data: HashMap::new(),
             has_changed: false,
             user_id: None,
@@ -0,0 +1,98 @@
+use axum::{Extension, Json};
+use axum::extract::Path;
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+use crate::api::ApiContext;
+use crate::api::auth::SessionUser;
+use crate::api::err::{ApiError, ApiResult};
+use crate::schemas::api_token::{ApiToken, TokenScope};
+
+const MAX_TOKENS: usize = 25;
+const MAX_TOKEN_DAYS: i64 = 365 * 5;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTokenPayload {
+    name: String,
+    scopes: Vec<TokenScope>,
+    /// Never expires when missing, capped at five years
+    expires_in_days: Option<i64>,
+}
+
+/// Created token along with its plaintext, which can't be retrieved afterwards
+#[derive(Serialize, ToSchema)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    token: ApiToken,
+    secret: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/tokens",
+    tag = "tokens",
+    responses((status = 200, body = Vec<ApiToken>)),
+    security(("session" = [])),
+)]
+pub async fn get_tokens(
+    user: SessionUser,
+    Extension(ctx): Extension<ApiContext>,
+) -> ApiResult<impl IntoResponse> {
+    ApiToken::list_by_user(user.id.clone(), &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/tokens",
+    tag = "tokens",
+    request_body = CreateTokenPayload,
+    responses((status = 200, body = CreatedToken)),
+    security(("session" = [])),
+)]
+pub async fn post_token(
+    user: SessionUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateTokenPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    if payload.name.trim().is_empty() {
+        return Err(ApiError::BadRequest(String::from("Token name can't be empty")));
+    }
+    if payload.scopes.is_empty() {
+        return Err(ApiError::BadRequest(String::from("Token needs at least one scope")));
+    }
+    let valid_for = payload.expires_in_days.map(|days| Duration::try_days(days.clamp(1, MAX_TOKEN_DAYS)).unwrap_or_default());
+    let mut conn = ctx.get_conn().await?;
+    if ApiToken::list_by_user(user.id.clone(), &mut conn).await?.len() >= MAX_TOKENS {
+        return Err(ApiError::BadRequest(format!("Users can have at most {MAX_TOKENS} tokens")));
+    }
+    let (token, secret) = ApiToken::new(user.id.clone(), payload.name, &payload.scopes, valid_for)?;
+    token.create(&mut conn).await?;
+
+    Ok(Json(CreatedToken { token, secret }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/tokens/{id}",
+    tag = "tokens",
+    params(("id" = String, Path, description = "Token id")),
+    responses((status = 200, description = "Empty object")),
+    security(("session" = [])),
+)]
+pub async fn delete_token(
+    user: SessionUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(token_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let token = ApiToken::get_by_id(token_id, &mut conn).await?;
+    if token.user_id != user.id {
+        return Err(ApiError::NotFound);
+    }
+    ApiToken::delete_by_id(token.id, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
@@ -0,0 +1,250 @@
+use axum::{Extension, Json};
+use axum::extract::Path;
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use chrono::Duration;
+use diesel_async::AsyncConnection;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+use crate::api::ApiContext;
+use crate::api::auth::{AuthUser, SessionUser};
+use crate::api::session::WritableSession;
+use crate::api::session::layer::destroy_user_sessions;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::api::permissions::{ManageInvites, ManageUsers, RequirePermission};
+use crate::schemas::User;
+use crate::schemas::invite::Invite;
+use crate::schemas::user::{validate_email, validate_password, validate_username, Permission, STAFF};
+
+const DEFAULT_INVITE_HOURS: i64 = 72;
+const MAX_INVITE_HOURS: i64 = 24 * 30;
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterPayload {
+    invite: String,
+    username: String,
+    password: String,
+    email: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangePasswordPayload {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PatchMePayload {
+    email: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateInvitePayload {
+    /// Restricts the invite to this address
+    email: Option<String>,
+    /// Flags the new user gets, owner only
+    #[serde(default)]
+    flags: i64,
+    valid_for_hours: Option<i64>,
+}
+
+/// Creates a user from an invite and signs them in
+#[utoipa::path(
+    post,
+    path = "/register",
+    tag = "auth",
+    request_body = RegisterPayload,
+    responses((status = 200, body = User)),
+    security(()),
+)]
+pub async fn register(
+    mut sess: WritableSession,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<RegisterPayload>, ApiError>
+) -> ApiResult<Json<User>> {
+    validate_username(&payload.username)?;
+    validate_password(&payload.password, &payload.username)?;
+    validate_email(&payload.email)?;
+    let invalid_invite = || ApiError::BadRequest(String::from("Invite is invalid or expired"));
+    let mut conn = ctx.get_conn().await?;
+    let mut invite = match Invite::get_by_code(payload.invite, &mut conn).await {
+        Err(ApiError::NotFound) => return Err(invalid_invite()),
+        Err(e) => return Err(e),
+        Ok(invite) if !invite.is_usable() => return Err(invalid_invite()),
+        Ok(invite) => invite
+    };
+    let verified_email = match &invite.email {
+        Some(email) if !email.eq_ignore_ascii_case(&payload.email) => {
+            return Err(ApiError::BadRequest(String::from("Invite is for another email address")))
+        },
+        Some(_) => true,
+        None => false
+    };
+
+    let mut user = User::new(payload.username, payload.password, payload.email);
+    user.verified_email = verified_email;
+    user.flags = invite.flags;
+    // the invite stays unused if creating the user fails
+    conn.transaction::<_, ApiError, _>(|conn| async {
+        invite.claim(user.id.clone(), conn).await?;
+        user.create(conn).await
+    }.scope_boxed()).await?;
+    sess.set_user(&user);
+
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/me",
+    tag = "users",
+    request_body = PatchMePayload,
+    responses((status = 200, body = User)),
+    security(("session" = [])),
+)]
+pub async fn patch_me(
+    SessionUser(mut user): SessionUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<PatchMePayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    if let Some(email) = payload.email {
+        validate_email(&email)?;
+        if email != user.email {
+            user.set_email(email, false, &mut conn).await?;
+        }
+    }
+
+    Ok(Json(user))
+}
+
+/// Changes the caller's password and signs out every other session, this one stays signed in.
+/// API tokens keep working, they're revoked separately through `/tokens`
+#[utoipa::path(
+    put,
+    path = "/me/password",
+    tag = "users",
+    request_body = ChangePasswordPayload,
+    responses((status = 200, description = "Empty object")),
+    security(("session" = [])),
+)]
+pub async fn put_password(
+    SessionUser(mut user): SessionUser,
+    mut sess: WritableSession,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<ChangePasswordPayload>, ApiError>
+) -> impl IntoResponse {
+    let mut conn = ctx.get_conn().await?;
+    if !user.compare_passwords(payload.current_password.clone()) {
+        return Err(ApiError::BadRequest(String::from("Current password is incorrect")));
+    }
+    if payload.new_password == payload.current_password {
+        return Err(ApiError::BadRequest(String::from("New password must be different")));
+    }
+    validate_password(&payload.new_password, &user.username)?;
+    conn.transaction::<_, ApiError, _>(|conn| async {
+        user.set_password(payload.new_password, conn).await?;
+        destroy_user_sessions(&user.id, conn).await.map_err(map_db_err)?;
+        Ok(())
+    }.scope_boxed()).await?;
+    // deleted along with the others, marking it changed stores it again once we respond
+    sess.set_user(&user);
+
+    Ok(Json(json!({})))
+}
+
+/// Permissions the caller's flags grant, for the dashboard to hide what they can't do
+#[utoipa::path(
+    get,
+    path = "/me/permissions",
+    tag = "users",
+    responses((status = 200, body = Vec<Permission>)),
+)]
+pub async fn get_my_permissions(user: AuthUser) -> Json<Vec<Permission>> {
+    Json(user.permissions())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    responses((status = 200, body = Vec<User>)),
+)]
+pub async fn get_users(
+    _: RequirePermission<ManageUsers>,
+    Extension(ctx): Extension<ApiContext>,
+) -> impl IntoResponse {
+    User::list_all(&mut ctx.get_conn().await?).await.map(Json)
+}
+
+/// Invites the caller created, or every invite for users that manage users
+#[utoipa::path(
+    get,
+    path = "/invites",
+    tag = "invites",
+    responses((status = 200, body = Vec<Invite>)),
+)]
+pub async fn get_invites(
+    user: RequirePermission<ManageInvites>,
+    Extension(ctx): Extension<ApiContext>,
+) -> impl IntoResponse {
+    let creator = if RequirePermission::<ManageUsers>::granted(&user) { None } else { Some(user.id.clone()) };
+
+    Invite::list(creator, &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/invites",
+    tag = "invites",
+    request_body = CreateInvitePayload,
+    responses((status = 200, body = Invite)),
+)]
+pub async fn post_invite(
+    user: RequirePermission<ManageInvites>,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateInvitePayload>, ApiError>
+) -> impl IntoResponse {
+    if payload.flags & !STAFF != 0 {
+        return Err(ApiError::BadRequest(String::from("Invites can only grant the staff flag")));
+    }
+    if payload.flags != 0 && !user.has_permission(Permission::GrantRoles) {
+        return Err(ApiError::Unauthorized);
+    }
+    if let Some(email) = &payload.email {
+        validate_email(email)?;
+    }
+    let hours = payload.valid_for_hours.unwrap_or(DEFAULT_INVITE_HOURS).clamp(1, MAX_INVITE_HOURS);
+    let invite = Invite::new(user.id.clone(), payload.email, payload.flags, Duration::try_hours(hours).unwrap_or_default());
+    invite.create(&mut ctx.get_conn().await?).await?;
+
+    Ok(Json(invite))
+}
+
+/// Revokes an unused invite, only users that manage users can revoke others' invites
+#[utoipa::path(
+    delete,
+    path = "/invites/{code}",
+    tag = "invites",
+    params(("code" = String, Path, description = "Invite code")),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_invite(
+    user: RequirePermission<ManageInvites>,
+    Extension(ctx): Extension<ApiContext>,
+    Path(code): Path<String>,
+) -> impl IntoResponse {
+    let mut conn = ctx.get_conn().await?;
+    let invite = Invite::get_by_code(code, &mut conn).await?;
+    if invite.created_by != user.id && !RequirePermission::<ManageUsers>::granted(&user) {
+        return Err(ApiError::NotFound);
+    }
+    if invite.used_by.is_some() {
+        return Err(ApiError::BadRequest(String::from("Invite was already used")));
+    }
+    Invite::delete_by_code(invite.code, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
@@ -0,0 +1,138 @@
+use axum::{Extension, Json};
+use axum::extract::Path;
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use crate::api::ApiContext;
+use crate::api::audit::RequestMeta;
+use crate::api::bots::get_owned_account;
+use crate::api::auth::AuthUser;
+use crate::api::err::{ApiError, ApiResult};
+use crate::bots::manager::BotCommand;
+use crate::discord_api::models::VoiceState;
+use crate::discord_api::snowflake::{ChannelId, GuildId};
+use crate::schemas::User;
+use crate::schemas::audit_log::AuditEntry;
+
+#[derive(Deserialize, ToSchema)]
+pub struct JoinVoicePayload {
+    channel_id: ChannelId,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PatchVoicePayload {
+    self_mute: Option<bool>,
+    self_deaf: Option<bool>,
+}
+
+/// Sends the voice command to the account and records it in the audit log
+async fn update_voice(ctx: &ApiContext, user: &User, meta: &RequestMeta, account_id: &str, command: BotCommand) -> ApiResult<VoiceState> {
+    let entry = AuditEntry::command(user.id.clone(), account_id.to_string(), &command).request(meta);
+    ctx.audit.around(entry, ctx.bots.update_voice(account_id, command)).await
+}
+
+/// Account's voice state in the guild, `null` when it isn't connected to voice there
+#[utoipa::path(
+    get,
+    path = "/accounts/{id}/guilds/{guild_id}/voice",
+    tag = "voice",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id")
+    ),
+    responses((status = 200, body = Option<VoiceState>)),
+)]
+pub async fn get_voice_state(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+) -> impl IntoResponse {
+    let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+
+    ctx.bots.voice_state(&account.id, guild_id).await.map(Json)
+}
+
+/// Joins the voice channel, or moves to it when already connected in the guild
+#[utoipa::path(
+    put,
+    path = "/accounts/{id}/guilds/{guild_id}/voice",
+    tag = "voice",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id")
+    ),
+    request_body = JoinVoicePayload,
+    responses((status = 200, body = VoiceState)),
+)]
+pub async fn put_voice_state(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+    WithRejection(Json(payload), _): WithRejection<Json<JoinVoicePayload>, ApiError>
+) -> impl IntoResponse {
+    let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+    let command = match ctx.bots.voice_state(&account.id, guild_id).await? {
+        Some(state) if state.channel_id.is_some() => BotCommand::MoveChannel(guild_id, payload.channel_id),
+        _ => BotCommand::JoinChannel(guild_id, payload.channel_id),
+    };
+
+    update_voice(&ctx, &user, &meta, &account.id, command).await.map(Json)
+}
+
+/// Sets self mute and/or deafen for the account's voice connection in the guild
+#[utoipa::path(
+    patch,
+    path = "/accounts/{id}/guilds/{guild_id}/voice",
+    tag = "voice",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id")
+    ),
+    request_body = PatchVoicePayload,
+    responses((status = 200, body = VoiceState)),
+)]
+pub async fn patch_voice_state(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+    WithRejection(Json(payload), _): WithRejection<Json<PatchVoicePayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+    let mut state = ctx.bots.voice_state(&account.id, guild_id).await?;
+    if let Some(self_mute) = payload.self_mute {
+        state = Some(update_voice(&ctx, &user, &meta, &account.id, BotCommand::SetSelfMute(guild_id, self_mute)).await?);
+    }
+    if let Some(self_deaf) = payload.self_deaf {
+        state = Some(update_voice(&ctx, &user, &meta, &account.id, BotCommand::SetSelfDeaf(guild_id, self_deaf)).await?);
+    }
+
+    Ok(Json(state))
+}
+
+/// Leaves the account's voice channel in the guild, returns the state after leaving
+#[utoipa::path(
+    delete,
+    path = "/accounts/{id}/guilds/{guild_id}/voice",
+    tag = "voice",
+    params(
+        ("id" = String, Path, description = "Controlled account id"),
+        ("guild_id" = GuildId, Path, description = "Guild id")
+    ),
+    responses((status = 200, body = VoiceState)),
+)]
+pub async fn delete_voice_state(
+    user: AuthUser,
+    meta: RequestMeta,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((account_id, guild_id)), _): WithRejection<Path<(String, GuildId)>, ApiError>,
+) -> impl IntoResponse {
+    let account = get_owned_account(account_id, &user, &mut ctx.get_conn().await?).await?;
+    let channel_id = ctx.bots.voice_state(&account.id, guild_id).await?
+        .and_then(|state| state.channel_id)
+        .ok_or_else(|| ApiError::BadRequest(String::from("Not in a voice channel in this guild")))?;
+
+    update_voice(&ctx, &user, &meta, &account.id, BotCommand::LeaveChannel(guild_id, channel_id)).await.map(Json)
+}
@@ -0,0 +1,207 @@
+use axum::{Extension, Json};
+use axum::extract::{Path, Query};
+use axum_core::response::IntoResponse;
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+use crate::api::ApiContext;
+use crate::api::auth::AuthUser;
+use crate::api::err::{ApiError, ApiResult};
+use crate::api::DbConn;
+use crate::discord_api::models::EditMessage;
+use crate::discord_api::snowflake::MessageId;
+use crate::schemas::webhook::Webhook;
+use crate::webhook::{ExecuteWebhook, WebhookTarget};
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWebhookPayload {
+    name: String,
+    url: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PatchWebhookPayload {
+    name: Option<String>,
+    url: Option<String>,
+}
+
+/// Loads webhook, returning not found if it doesn't belong to `uid`
+async fn get_owned_webhook(webhook_id: String, uid: &str, conn: &mut DbConn) -> ApiResult<Webhook> {
+    let hook = Webhook::get_by_id(webhook_id, conn).await?;
+    if hook.created_by != uid {
+        return Err(ApiError::NotFound);
+    }
+    Ok(hook)
+}
+
+fn validate_name(name: &str) -> ApiResult<()> {
+    if name.trim().is_empty() {
+        return Err(ApiError::BadRequest(String::from("Webhook name can't be empty")));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    responses((status = 200, body = Vec<Webhook>)),
+)]
+pub async fn get_webhooks(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+) -> impl IntoResponse {
+    Webhook::list_by_creator(user.id.clone(), &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookPayload,
+    responses((status = 200, body = Webhook)),
+)]
+pub async fn post_webhook(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Json(payload), _): WithRejection<Json<CreateWebhookPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    validate_name(&payload.name)?;
+    let hook = Webhook::new(payload.name, &payload.url, user.id.clone())?;
+    hook.create(&mut ctx.get_conn().await?).await?;
+
+    Ok(Json(hook))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    responses((status = 200, body = Webhook)),
+)]
+pub async fn get_webhook(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(webhook_id): Path<String>,
+) -> impl IntoResponse {
+    get_owned_webhook(webhook_id, &user.id, &mut ctx.get_conn().await?).await.map(Json)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    request_body = PatchWebhookPayload,
+    responses((status = 200, body = Webhook)),
+)]
+pub async fn patch_webhook(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(webhook_id): Path<String>,
+    WithRejection(Json(payload), _): WithRejection<Json<PatchWebhookPayload>, ApiError>
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let mut hook = get_owned_webhook(webhook_id, &user.id, &mut conn).await?;
+    if let Some(name) = payload.name {
+        validate_name(&name)?;
+        hook.rename(name, &mut conn).await?;
+    }
+    if let Some(url) = payload.url {
+        hook.set_url(&url, &mut conn).await?;
+    }
+
+    Ok(Json(hook))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_webhook(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(webhook_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let mut conn = ctx.get_conn().await?;
+    let hook = get_owned_webhook(webhook_id, &user.id, &mut conn).await?;
+    Webhook::delete_by_id(hook.id, &mut conn).await?;
+
+    Ok(Json(json!({})))
+}
+
+/// Posts message through the webhook, `?wait=true` returns the created message
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/execute",
+    tag = "webhooks",
+    params(
+        ("id" = String, Path, description = "Webhook id"),
+        WebhookTarget
+    ),
+    request_body(content = serde_json::Value, description = "Discord's execute webhook body"),
+    responses((status = 200, body = serde_json::Value, description = "Discord's message object, null unless `wait` is set")),
+)]
+pub async fn post_webhook_execute(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    Path(webhook_id): Path<String>,
+    Query(target): Query<WebhookTarget>,
+    WithRejection(Json(payload), _): WithRejection<Json<ExecuteWebhook>, ApiError>
+) -> impl IntoResponse {
+    let hook = get_owned_webhook(webhook_id, &user.id, &mut ctx.get_conn().await?).await?;
+
+    hook.client()?.execute(&payload, &target).await.map(Json)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/webhooks/{id}/messages/{message_id}",
+    tag = "webhooks",
+    params(
+        ("id" = String, Path, description = "Webhook id"),
+        ("message_id" = MessageId, Path, description = "Message id"),
+        WebhookTarget
+    ),
+    request_body(content = serde_json::Value, description = "Discord's edit message body"),
+    responses((status = 200, body = serde_json::Value, description = "Discord's message object")),
+)]
+pub async fn patch_webhook_message(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((webhook_id, message_id)), _): WithRejection<Path<(String, MessageId)>, ApiError>,
+    Query(target): Query<WebhookTarget>,
+    WithRejection(Json(edit), _): WithRejection<Json<EditMessage>, ApiError>
+) -> impl IntoResponse {
+    let hook = get_owned_webhook(webhook_id, &user.id, &mut ctx.get_conn().await?).await?;
+
+    hook.client()?.edit_message(message_id, &edit, target.thread_id).await.map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}/messages/{message_id}",
+    tag = "webhooks",
+    params(
+        ("id" = String, Path, description = "Webhook id"),
+        ("message_id" = MessageId, Path, description = "Message id"),
+        WebhookTarget
+    ),
+    responses((status = 200, description = "Empty object")),
+)]
+pub async fn delete_webhook_message(
+    user: AuthUser,
+    Extension(ctx): Extension<ApiContext>,
+    WithRejection(Path((webhook_id, message_id)), _): WithRejection<Path<(String, MessageId)>, ApiError>,
+    Query(target): Query<WebhookTarget>,
+) -> ApiResult<impl IntoResponse> {
+    let hook = get_owned_webhook(webhook_id, &user.id, &mut ctx.get_conn().await?).await?;
+    hook.client()?.delete_message(message_id, target.thread_id).await?;
+
+    Ok(Json(json!({})))
+}
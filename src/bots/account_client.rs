@@ -1,97 +1,169 @@
-use std::future::Future;
 use std::sync::{Arc};
 use tokio::sync::Mutex;
-use async_channel::{Receiver, RecvError, Sender, unbounded};
-use futures_util::{future, SinkExt, StreamExt};
-use log::{error, info};
-use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
-use serde::{Deserialize, Serialize};
-use crate::{BASE_URL, USER_AGENT};
+use async_channel::{Sender, unbounded};
+use axum::http::StatusCode;
+use serde_json::json;
 use crate::api::err::{ApiError, ApiResult};
-use crate::bots::manager::BotCommand;
+use crate::bots::dry_run::DryRun;
+use crate::bots::events::EventHub;
+use crate::bots::voice::VoiceStates;
+use crate::bots::manager::{BotCommand, CommandTransport};
 use crate::bots::ws::ws_loop;
-use crate::discord_api::{DiscordApiResponse};
+use crate::db::gen_id;
+use crate::discord_api::DiscordClient;
+use crate::discord_api::errors::DiscordErrorCode;
+use crate::discord_api::gateway::IdentifyOptions;
+use crate::discord_api::permissions::{channel_permissions, CONNECT, MOVE_MEMBERS, VIEW_CHANNEL};
+use crate::discord_api::snowflake::{ChannelId, GuildId, MessageId, UserId};
+use crate::discord_api::token::TokenKind;
+use crate::discord_api::models::{DiscordUser, EditMessage, Message, ModifyCurrentUser, OutgoingMessage};
 use crate::schemas::controlled_account::ControlledAccount;
 
-/// Partial version of Discord's internal user struct
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DiscordUser {
-    id: String,
-    username: String,
-    discriminator: String,
-}
-
 #[derive(Clone, Debug)]
 pub struct BotClient {
-    pub req_client: reqwest::Client,
-    pub command_chan: Option<Receiver<BotCommand>>,
-    pub account_id: String,
+    pub api: DiscordClient,
+    /// ID for database
+    pub id: String,
+    pub account_id: UserId,
     pub username: String,
     pub account_token: String,
-    pub created_by: String
-}
-
-fn map_err_invalid (e: impl std::error::Error) -> ApiError {
-    error!("{e}");
-    ApiError::InternalError
-}
-
-pub struct StallChanClosed<T> {
-    inner: Arc<Sender<T>>
-}
-
-impl<T> Future for StallChanClosed<T> {
-    type Output = ();
-
-    fn poll(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<()> {
-        if self.inner.is_closed() {
-            std::task::Poll::Ready(())
-        } else {
-            std::task::Poll::Pending
-        }
-    }
+    pub token_kind: TokenKind,
+    pub created_by: String,
+    pub dry_run: Arc<DryRun>,
+    pub identify: IdentifyOptions,
+    /// Shared with the manager, see [`crate::bots::manager::BotManager::events`]
+    pub events: Arc<EventHub>,
+    /// Filled in by the gateway connection
+    pub voice: Arc<VoiceStates>,
 }
 
 impl BotClient {
-    pub async fn new(token: String, created_by: String) -> ApiResult<BotClient> {
-        // let (mut conn, _r) = connect_async("wss://discord.com").await?;
-        let req_client = reqwest::ClientBuilder::new().user_agent(USER_AGENT).default_headers({
-            let mut dft_headers = HeaderMap::new();
-            dft_headers.insert(AUTHORIZATION, HeaderValue::from_str(token.as_str()).map_err(map_err_invalid)?);
-            dft_headers
-        }).build().map_err(map_err_invalid.clone())?;
-
-        let resp = req_client.get(format!("{}/users/@me", BASE_URL)).send().await.map_err(map_err_invalid)?.json::<DiscordApiResponse<DiscordUser>>().await.map_err(map_err_invalid)?;
-        let user = match resp.into_result() {
-            Err(_err) => {
+    /// Validates token against Discord and creates client for a new account
+    pub async fn new(token: String, token_kind: TokenKind, created_by: String) -> ApiResult<BotClient> {
+        let id = gen_id();
+        let api = DiscordClient::new(token.as_str(), token_kind)?.for_account(&id);
+        let user = match api.get_current_user().await {
+            Err(ApiError::Discord(details)) if details.status == 401 || details.code == DiscordErrorCode::InvalidToken => {
                 return Err(ApiError::BadRequest(String::from("Invalid token")))
             },
+            Err(err) => return Err(err),
             Ok(user) => user
         };
 
         Ok(BotClient {
-            req_client,
-            command_chan: None,
+            api,
+            id,
             account_id: user.id,
             username: user.username,
             account_token: token,
-            created_by
+            token_kind,
+            created_by,
+            dry_run: Arc::new(DryRun::default()),
+            identify: IdentifyOptions::default(),
+            events: Arc::default(),
+            voice: Arc::default(),
+        })
+    }
+
+    /// Client for an account that's already stored, doesn't revalidate the token
+    pub fn from_account(account: &ControlledAccount) -> ApiResult<BotClient> {
+        Ok(BotClient {
+            api: DiscordClient::new(account.token(), account.token_kind())?.for_account(&account.id),
+            id: account.id.clone(),
+            account_id: account.discord_id,
+            username: account.username.clone(),
+            account_token: account.token().to_string(),
+            token_kind: account.token_kind(),
+            created_by: account.created_by.clone(),
+            dry_run: Arc::new(DryRun::new(Arc::default(), account.dry_run)),
+            identify: account.identify_options(),
+            events: Arc::default(),
+            voice: Arc::default(),
         })
     }
 
-    pub fn spawn_ws_conn(&self) -> impl Future<Output=Sender<BotCommand>> {
-        let token = self.account_token.clone();
-        let session_id = Arc::new(Mutex::new(None::<String>));
-        async move {
-            let (s, r) = unbounded();
-            let r = Arc::new(r);
-            ws_loop(token.clone(), session_id, r).await;
-            s
+    /// Spawns the gateway connection, returns the channel used to send it commands. The channel
+    /// is closed once the connection is gone. Tokens without gateway access get a channel that's only
+    /// drained, the manager never routes gateway commands to them
+    pub fn spawn_ws_conn(&self) -> Sender<BotCommand> {
+        let (s, r) = unbounded();
+        if self.token_kind.has_gateway() {
+            let session_id = Arc::new(Mutex::new(None::<String>));
+            tokio::spawn(ws_loop(self.clone(), session_id, Arc::new(r)));
+        } else {
+            tokio::spawn(async move { while r.recv().await.is_ok() {} });
         }
+        s
+    }
+
+    /// Set (or reset with `None`) the account's nickname in a guild
+    pub async fn set_nickname(&self, guild_id: GuildId, nick: Option<String>) -> ApiResult<()> {
+        let route = format!("/guilds/{}/members/@me", guild_id);
+        if self.dry_run.intercept(&self.id, CommandTransport::Rest, json!({ "method": "PATCH", "route": route, "body": { "nick": nick } })) {
+            return Ok(());
+        }
+        self.api.modify_current_member(guild_id, nick).await?;
+        Ok(())
+    }
+
+    /// Posts message in channel, `None` in dry-run mode
+    pub async fn send_message(&self, channel_id: ChannelId, message: &OutgoingMessage) -> ApiResult<Option<Message>> {
+        let route = format!("/channels/{}/messages", channel_id);
+        let files = message.files.iter().map(|f| f.filename.as_str()).collect::<Vec<_>>();
+        if self.dry_run.intercept(&self.id, CommandTransport::Rest, json!({ "method": "POST", "route": route, "body": &message.message, "files": files })) {
+            return Ok(None);
+        }
+        self.api.create_message(channel_id, message).await.map(Some)
+    }
+
+    pub async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, edit: &EditMessage) -> ApiResult<Option<Message>> {
+        let route = format!("/channels/{}/messages/{}", channel_id, message_id);
+        if self.dry_run.intercept(&self.id, CommandTransport::Rest, json!({ "method": "PATCH", "route": route, "body": edit })) {
+            return Ok(None);
+        }
+        self.api.edit_message(channel_id, message_id, edit).await.map(Some)
+    }
+
+    pub async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> ApiResult<()> {
+        let route = format!("/channels/{}/messages/{}", channel_id, message_id);
+        if self.dry_run.intercept(&self.id, CommandTransport::Rest, json!({ "method": "DELETE", "route": route })) {
+            return Ok(());
+        }
+        self.api.delete_message(channel_id, message_id).await
+    }
+
+    /// Checks the account could connect to the voice channel, so failures get a clear error
+    /// instead of Discord silently ignoring the voice state update
+    pub async fn check_voice_access(&self, guild_id: GuildId, channel_id: ChannelId) -> ApiResult<()> {
+        let channel = self.api.get_channel(channel_id).await?;
+        // 2 voice, 13 stage
+        if channel.guild_id != Some(guild_id) || ![2, 13].contains(&channel.kind) {
+            return Err(ApiError::BadRequest(String::from("Not a voice channel of this guild")));
+        }
+        let guild = self.api.get_guild(guild_id).await?;
+        let member = self.api.get_guild_member(guild_id, self.account_id).await?;
+        let permissions = channel_permissions(&guild, &member, self.account_id, &channel);
+        if permissions & (VIEW_CHANNEL | CONNECT) != VIEW_CHANNEL | CONNECT {
+            return Err(ApiError::Custom(StatusCode::FORBIDDEN, String::from("Missing permission to connect to the channel"), None));
+        }
+        let user_limit = channel.user_limit.unwrap_or(0).max(0) as usize;
+        let already_in = self.voice.get(guild_id, self.account_id).and_then(|state| state.channel_id) == Some(channel_id);
+        // members that can move others ignore the limit
+        if channel.kind == 2 && user_limit > 0 && !already_in && permissions & MOVE_MEMBERS == 0 && self.voice.occupants(guild_id, channel_id) >= user_limit {
+            return Err(ApiError::Custom(StatusCode::CONFLICT, String::from("Voice channel user limit reached"), None));
+        }
+        Ok(())
+    }
+
+    /// Changes the account's username and/or avatar
+    pub async fn modify_profile(&self, payload: ModifyCurrentUser) -> ApiResult<Option<DiscordUser>> {
+        if self.dry_run.intercept(&self.id, CommandTransport::Rest, json!({ "method": "PATCH", "route": "/users/@me", "body": &payload })) {
+            return Ok(None);
+        }
+        self.api.modify_current_user(&payload).await.map(Some)
     }
 
     pub fn to_discord_account(&self) -> ControlledAccount {
-        ControlledAccount::new(&self)
+        ControlledAccount::new(self)
     }
 }
-
@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use crate::discord_api::snowflake::{ChannelId, GuildId, UserId};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DumbUser {
-    id: String,
+    id: UserId,
     username: Option<String>,
     public_flags: Option<i64>,
     global_name: Option<String>,
@@ -11,20 +12,6 @@ pub struct DumbUser {
     avatar: Option<String>
 }
 
-pub struct DiscordUser {
-    avatar: Option<String>,
-    communication_disabled_until: Option<String>,
-    deaf: Option<bool>,
-    flags: i32,
-    joined_at: String,
-    mute: Option<bool>,
-    nick: Option<String>,
-    pending: Option<bool>,
-    premium_since: Option<String>,
-    roles: Vec<String>,
-    user: DumbUser
-}
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ReadyGuild {
     /*
@@ -35,15 +22,23 @@ pub struct ReadyGuild {
     version: i64,
 
      */
-    pub id: String,
+    pub id: GuildId,
+    // bot accounts only get unavailable guilds ({id, unavailable}) in ready
+    #[serde(default)]
     pub name: String,
 
+    #[serde(default)]
     pub joined_at: String,
+    #[serde(default)]
     pub large: bool,
     pub unavailable: Option<bool>,
+    #[serde(default)]
     pub member_count: i32,
+    #[serde(default)]
     pub voice_states: Vec<Value>,
+    #[serde(default)]
     pub members: Vec<Value>,
+    #[serde(default)]
     pub channels: Vec<Value>,
     // threads: Vec<Value>,
     // presences: Vec<Value>,
@@ -57,7 +52,7 @@ pub enum IncomingWsEvent {
     PresenceUpdate {
         user: DumbUser,
         status: String,
-        guild_id: Option<String>,
+        guild_id: Option<GuildId>,
     },
     Hello {
         heartbeat_interval: i32,
@@ -79,10 +74,12 @@ pub enum WsMessageType {
         os: String,
         browser: String,
         device: String,
+        /// Only sent by bot applications
+        intents: Option<u64>,
     },
     UpdateVoiceState {
-        guild_id: String,
-        channel_id: Option<String>,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
         self_mute: bool,
         self_deaf: bool,
     },
@@ -118,7 +115,23 @@ impl WsMessageType {
                     d: last_ack.map(|v| Value::Number(serde_json::Number::from(v)))
                 }
             },
-            WsMessageType::Identify {token, os, browser, device} => {
+            WsMessageType::Identify {token, os, browser, device, intents: Some(intents)} => {
+                WsMessage {
+                    t: None,
+                    s: None,
+                    op: 2,
+                    d: Some(json!({
+                        "token": token,
+                        "intents": intents,
+                        "properties": {
+                            "os": os,
+                            "browser": browser,
+                            "device": device,
+                        }
+                    }))
+                }
+            },
+            WsMessageType::Identify {token, os, browser, device, intents: None} => {
                 WsMessage {
                     t: None,
                     s: None,
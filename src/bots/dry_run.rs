@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::bots::manager::CommandTransport;
+
+/// Number of intended actions kept per bot
+const MAX_RECORDED_ACTIONS: usize = 100;
+
+/// Something a bot would have sent to Discord if it wasn't in dry-run mode
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct IntendedAction {
+    pub at: DateTime<Utc>,
+    pub transport: CommandTransport,
+    pub action: Value,
+}
+
+/// Dry-run state of a single bot. A bot is in dry-run mode when either the manager wide flag or
+/// its own flag is set, it then still receives gateway events but outbound commands and REST
+/// mutations are only recorded.
+#[derive(Debug, Default)]
+pub struct DryRun {
+    global: Arc<AtomicBool>,
+    account: AtomicBool,
+    actions: Mutex<VecDeque<IntendedAction>>,
+}
+
+impl DryRun {
+    pub fn new(global: Arc<AtomicBool>, account: bool) -> Self {
+        Self {
+            global,
+            account: AtomicBool::new(account),
+            actions: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.global.load(Ordering::Relaxed) || self.account.load(Ordering::Relaxed)
+    }
+
+    pub fn account_flag(&self) -> bool {
+        self.account.load(Ordering::Relaxed)
+    }
+
+    pub fn set_account_flag(&self, enabled: bool) {
+        self.account.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Records action if dry-run is active, returns true when the caller shouldn't send it
+    pub fn intercept(&self, account_id: &str, transport: CommandTransport, action: impl Serialize) -> bool {
+        if !self.is_active() {
+            return false;
+        }
+        let action = serde_json::to_value(action).unwrap_or_default();
+        info!("[dry run] {account_id} {transport:?} {action}");
+        let mut actions = self.actions.lock().unwrap();
+        if actions.len() >= MAX_RECORDED_ACTIONS {
+            actions.pop_front();
+        }
+        actions.push_back(IntendedAction { at: Utc::now(), transport, action });
+        true
+    }
+
+    /// Recorded actions, oldest first
+    pub fn actions(&self) -> Vec<IntendedAction> {
+        self.actions.lock().unwrap().iter().cloned().collect()
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use utoipa::IntoParams;
+use crate::bots::manager::CommandTransport;
+use crate::discord_api::snowflake::GuildId;
+
+/// Number of events kept per account for subscribers that join late
+const REPLAY_BUFFER_SIZE: usize = 50;
+/// Events a slow subscriber can fall behind by before it starts missing some
+const CHANNEL_CAPACITY: usize = 256;
+
+/// State of a bot's gateway connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting,
+    Ready,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum BotEventKind {
+    /// Dispatch received on the gateway, `event` is Discord's event name, e.g. `MESSAGE_CREATE`
+    Gateway {
+        event: String,
+        guild_id: Option<GuildId>,
+        payload: Value,
+    },
+    ConnectionState(ConnectionState),
+    /// Command sent through the api, gateway commands succeed once they're queued
+    CommandResult {
+        transport: CommandTransport,
+        /// See [`crate::bots::manager::BotCommand::name`], the command itself isn't sent since it may carry files
+        command: &'static str,
+        guild_id: Option<GuildId>,
+        success: bool,
+        error: Option<String>,
+    },
+}
+
+/// Something that happened to a running bot, streamed to the dashboard
+#[derive(Debug, Clone, Serialize)]
+pub struct BotEvent {
+    pub at: DateTime<Utc>,
+    pub account_id: String,
+    #[serde(flatten)]
+    pub kind: BotEventKind,
+}
+
+impl BotEvent {
+    /// Name filters match against, the gateway event name for dispatches and the kind otherwise
+    pub fn name(&self) -> &str {
+        match &self.kind {
+            BotEventKind::Gateway { event, .. } => event.as_str(),
+            BotEventKind::ConnectionState(_) => "connection_state",
+            BotEventKind::CommandResult { .. } => "command_result",
+        }
+    }
+
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match &self.kind {
+            BotEventKind::Gateway { guild_id, .. } |
+            BotEventKind::CommandResult { guild_id, .. } => *guild_id,
+            BotEventKind::ConnectionState(_) => None,
+        }
+    }
+}
+
+/// Which events a subscriber wants, unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventFilter {
+    /// Comma separated event names, see [`BotEvent::name`]
+    pub types: Option<String>,
+    /// Events that aren't tied to a guild, like connection state changes, are always kept
+    pub guild_id: Option<GuildId>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &BotEvent) -> bool {
+        if let Some(types) = &self.types {
+            if !types.split(',').any(|t| t.trim().eq_ignore_ascii_case(event.name())) {
+                return false;
+            }
+        }
+        match (self.guild_id, event.guild_id()) {
+            (Some(wanted), Some(guild_id)) => wanted == guild_id,
+            _ => true,
+        }
+    }
+}
+
+/// Fans an account's events out to subscribers, keeping the latest ones around to replay
+#[derive(Debug)]
+pub struct EventHub {
+    sender: broadcast::Sender<BotEvent>,
+    recent: Mutex<VecDeque<BotEvent>>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl EventHub {
+    pub fn publish(&self, account_id: &str, kind: BotEventKind) {
+        let event = BotEvent { at: Utc::now(), account_id: account_id.to_string(), kind };
+        // sent while holding the buffer so a subscriber never sees an event twice or misses one
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= REPLAY_BUFFER_SIZE {
+            recent.pop_front();
+        }
+        recent.push_back(event.clone());
+        // no subscribers isn't an error
+        let _ = self.sender.send(event);
+    }
+
+    /// Recent events, oldest first, and a receiver for the ones after them
+    pub fn subscribe(&self) -> (Vec<BotEvent>, broadcast::Receiver<BotEvent>) {
+        let recent = self.recent.lock().unwrap();
+        (recent.iter().cloned().collect(), self.sender.subscribe())
+    }
+}
+
+/// Publishes to a hub on behalf of one account
+#[derive(Debug, Clone)]
+pub struct EventSink {
+    pub account_id: String,
+    pub hub: Arc<EventHub>,
+}
+
+impl EventSink {
+    pub fn publish(&self, kind: BotEventKind) {
+        self.hub.publish(&self.account_id, kind);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discord_api::snowflake::Snowflake;
+    use super::*;
+
+    fn gateway(event: &str, guild_id: Option<u64>) -> BotEvent {
+        BotEvent {
+            at: Utc::now(),
+            account_id: String::from("account"),
+            kind: BotEventKind::Gateway { event: event.to_string(), guild_id: guild_id.map(|id| GuildId(Snowflake(id))), payload: Value::Null },
+        }
+    }
+
+    fn connection_state() -> BotEvent {
+        BotEvent { at: Utc::now(), account_id: String::from("account"), kind: BotEventKind::ConnectionState(ConnectionState::Ready) }
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = EventFilter::default();
+        assert!(filter.matches(&gateway("MESSAGE_CREATE", Some(1))));
+        assert!(filter.matches(&connection_state()));
+    }
+
+    #[test]
+    fn filters_types() {
+        let filter = EventFilter { types: Some(String::from("message_create, connection_state")), guild_id: None };
+        assert!(filter.matches(&gateway("MESSAGE_CREATE", None)));
+        assert!(filter.matches(&connection_state()));
+        assert!(!filter.matches(&gateway("TYPING_START", None)));
+    }
+
+    #[test]
+    fn filters_guild() {
+        let filter = EventFilter { types: None, guild_id: Some(GuildId(Snowflake(1))) };
+        assert!(filter.matches(&gateway("MESSAGE_CREATE", Some(1))));
+        assert!(!filter.matches(&gateway("MESSAGE_CREATE", Some(2))));
+        assert!(filter.matches(&gateway("MESSAGE_CREATE", None)));
+        assert!(filter.matches(&connection_state()));
+    }
+}
@@ -1,29 +1,486 @@
-use std::sync::Mutex;
-use async_channel::{Receiver, Sender, unbounded};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use async_channel::Sender;
+use futures_util::future::join_all;
+use log::{error, info};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::sync::RwLock;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time;
+use utoipa::ToSchema;
+use crate::api::err::{ApiError, ApiResult};
 use crate::bots::account_client::BotClient;
+use crate::bots::dry_run::DryRun;
+use crate::bots::events::{BotEvent, BotEventKind, ConnectionState, EventHub};
+use crate::discord_api::models::{EditMessage, Message, OutgoingMessage, VoiceState};
+use crate::discord_api::snowflake::{ChannelId, GuildId, MessageId};
+use crate::schemas::controlled_account::ControlledAccount;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum BotCommand {
-    LeaveChannel(String, String),
-    JoinChannel(String, String),
+    /// Leave voice in (guild_id, channel_id)
+    LeaveChannel(GuildId, ChannelId),
+    /// Join voice channel (guild_id, channel_id)
+    JoinChannel(GuildId, ChannelId),
+    /// Move to another voice channel in the same guild (guild_id, channel_id)
+    MoveChannel(GuildId, ChannelId),
+    /// Set self mute for the voice connection in guild (guild_id, muted)
+    SetSelfMute(GuildId, bool),
+    /// Set self deafen for the voice connection in guild (guild_id, deafened)
+    SetSelfDeaf(GuildId, bool),
+    SetPresence {
+        status: String,
+        activities: Option<Vec<Value>>,
+        afk: bool,
+    },
+    /// Set nickname in guild (guild_id, nickname), `None` resets it. Sent over REST
+    SetNickname(GuildId, Option<String>),
+    /// Post message in channel (channel_id, message). Sent over REST
+    SendMessage(ChannelId, OutgoingMessage),
+    /// Edit message (channel_id, message_id, edit). Sent over REST
+    EditMessage(ChannelId, MessageId, EditMessage),
+    /// Delete message (channel_id, message_id). Sent over REST
+    DeleteMessage(ChannelId, MessageId),
     Disconnect,
 }
 
+/// How a [`BotCommand`] reaches Discord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandTransport {
+    Gateway,
+    Rest,
+}
+
+impl BotCommand {
+    pub fn transport(&self) -> CommandTransport {
+        match self {
+            BotCommand::SetNickname(..) |
+            BotCommand::SendMessage(..) |
+            BotCommand::EditMessage(..) |
+            BotCommand::DeleteMessage(..) => CommandTransport::Rest,
+            _ => CommandTransport::Gateway,
+        }
+    }
+
+    /// Name the command is tagged with when serialized
+    pub fn name(&self) -> &'static str {
+        match self {
+            BotCommand::LeaveChannel(..) => "leave_channel",
+            BotCommand::JoinChannel(..) => "join_channel",
+            BotCommand::MoveChannel(..) => "move_channel",
+            BotCommand::SetSelfMute(..) => "set_self_mute",
+            BotCommand::SetSelfDeaf(..) => "set_self_deaf",
+            BotCommand::SetPresence { .. } => "set_presence",
+            BotCommand::SetNickname(..) => "set_nickname",
+            BotCommand::SendMessage(..) => "send_message",
+            BotCommand::EditMessage(..) => "edit_message",
+            BotCommand::DeleteMessage(..) => "delete_message",
+            BotCommand::Disconnect => "disconnect",
+        }
+    }
+
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match self {
+            BotCommand::LeaveChannel(guild_id, _) |
+            BotCommand::JoinChannel(guild_id, _) |
+            BotCommand::MoveChannel(guild_id, _) |
+            BotCommand::SetSelfMute(guild_id, _) |
+            BotCommand::SetSelfDeaf(guild_id, _) |
+            BotCommand::SetNickname(guild_id, _) => Some(*guild_id),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a command sent to one account of a group
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CommandResult {
+    pub account_id: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// How long Discord gets to confirm a voice state change
+const VOICE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn not_connected() -> ApiError {
+    ApiError::Custom(StatusCode::SERVICE_UNAVAILABLE, String::from("Bot is not connected"), None)
+}
+
+/// Voice state a voice command should end in
+#[derive(Debug, Clone, Copy)]
+struct ExpectedVoice {
+    channel_id: Option<ChannelId>,
+    self_mute: Option<bool>,
+    self_deaf: Option<bool>,
+}
+
+impl ExpectedVoice {
+    fn matches(&self, state: &VoiceState) -> bool {
+        state.channel_id == self.channel_id
+            && self.self_mute.is_none_or(|mute| state.self_mute == mute)
+            && self.self_deaf.is_none_or(|deaf| state.self_deaf == deaf)
+    }
+
+    /// State the bot would be in, used in dry-run mode where nothing is confirmed
+    fn apply(&self, state: VoiceState) -> VoiceState {
+        VoiceState {
+            channel_id: self.channel_id,
+            self_mute: self.self_mute.unwrap_or(state.self_mute),
+            self_deaf: self.self_deaf.unwrap_or(state.self_deaf),
+            ..state
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RunningBot {
+    pub client: BotClient,
+    commands: Sender<BotCommand>,
+}
+
+/// Keeps track of every running bot, keyed by controlled account id
 #[derive(Debug)]
 pub struct BotManager {
-    bots: RwLock<Vec<(Option<Mutex<Sender<BotCommand>>>, BotClient)>>,
+    bots: RwLock<HashMap<String, RunningBot>>,
+    /// Puts every bot in dry-run mode, starts set when `DRY_RUN=true`
+    dry_run: Arc<AtomicBool>,
+    /// Kept across restarts so subscribers stay attached and see the reconnect
+    events: Mutex<HashMap<String, Arc<EventHub>>>,
+    /// Kept across restarts like `events`, so recorded actions aren't lost and clients of stopped
+    /// bots record into the same place
+    dry_runs: Mutex<HashMap<String, Arc<DryRun>>>,
+}
+
+impl Default for BotManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BotManager {
     pub fn new() -> Self {
+        let dry_run = env::var("DRY_RUN").map(|v| v == "true").unwrap_or(false);
         Self {
-            bots: RwLock::new(vec![]),
+            bots: RwLock::new(HashMap::new()),
+            dry_run: Arc::new(AtomicBool::new(dry_run)),
+            events: Mutex::new(HashMap::new()),
+            dry_runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn global_dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::Relaxed)
+    }
+
+    pub fn set_global_dry_run(&self, dry_run: bool) {
+        info!("global dry run {}", if dry_run { "enabled" } else { "disabled" });
+        self.dry_run.store(dry_run, Ordering::Relaxed);
+    }
+
+    pub async fn new_bot(&self, mut new_client: BotClient) {
+        new_client.dry_run = self.dry_run(&new_client.id, new_client.dry_run.account_flag());
+        new_client.events = self.events(&new_client.id);
+        let commands = new_client.spawn_ws_conn();
+        self.bots.write().await.insert(new_client.id.clone(), RunningBot {
+            client: new_client,
+            commands,
+        });
+    }
+
+    /// Starts a bot for the stored account, replacing the one already running for it
+    pub async fn start(&self, account: &ControlledAccount) -> ApiResult<()> {
+        let client = BotClient::from_account(account)?;
+        self.stop(&account.id).await;
+        self.new_bot(client).await;
+        Ok(())
+    }
+
+    /// Disconnects the bot and stops tracking it, returns false if it wasn't running
+    pub async fn stop(&self, account_id: &str) -> bool {
+        let bot = match self.bots.write().await.remove(account_id) {
+            None => return false,
+            Some(bot) => bot
+        };
+        // remaining messages are still delivered after close so the disconnect goes through
+        let _ = bot.commands.send(BotCommand::Disconnect).await;
+        bot.commands.close();
+        true
+    }
+
+    /// Starts bots for enabled accounts that aren't running or whose connection died, stops bots
+    /// whose account is gone, disabled or has an invalid token
+    pub async fn reconcile(&self, accounts: &[ControlledAccount]) {
+        let wanted = accounts.iter()
+            .filter(|acc| acc.is_runnable())
+            .map(|acc| (acc.id.as_str(), acc))
+            .collect::<HashMap<_, _>>();
+        let (unwanted, alive) = {
+            let bots = self.bots.read().await;
+            let unwanted = bots.keys().filter(|id| !wanted.contains_key(id.as_str())).cloned().collect::<Vec<_>>();
+            let alive = bots.iter().filter(|(_, bot)| !bot.commands.is_closed()).map(|(id, _)| id.clone()).collect::<HashSet<_>>();
+            (unwanted, alive)
+        };
+
+        for account_id in unwanted {
+            info!("stopping bot {account_id}, account is gone, disabled or its token is invalid");
+            self.stop(&account_id).await;
+        }
+        for (account_id, account) in wanted {
+            if alive.contains(account_id) {
+                continue;
+            }
+            info!("starting bot {account_id}");
+            if let Err(e) = self.start(account).await {
+                error!("couldn't start bot {account_id}: {e}");
+            }
+        }
+    }
+
+    /// Client of the running bot, or a fresh one sharing the account's dry-run state if the
+    /// account's bot isn't running
+    pub async fn client_for(&self, account: &ControlledAccount) -> ApiResult<BotClient> {
+        if let Some(client) = self.get_client(&account.id).await {
+            return Ok(client);
+        }
+        let mut client = BotClient::from_account(account)?;
+        client.dry_run = self.dry_run(&account.id, account.dry_run);
+        client.events = self.events(&account.id);
+        Ok(client)
+    }
+
+    /// Event hub of the account, created on first use whether or not its bot is running
+    pub fn events(&self, account_id: &str) -> Arc<EventHub> {
+        self.events.lock().unwrap().entry(account_id.to_string()).or_default().clone()
+    }
+
+    /// Dry-run state of the account sharing the manager wide flag, created on first use. The
+    /// account's own flag is set to `account_flag`, the stored one
+    pub fn dry_run(&self, account_id: &str, account_flag: bool) -> Arc<DryRun> {
+        let dry_run = self.dry_runs.lock().unwrap()
+            .entry(account_id.to_string())
+            .or_insert_with(|| Arc::new(DryRun::new(self.dry_run.clone(), account_flag)))
+            .clone();
+        dry_run.set_account_flag(account_flag);
+        dry_run
+    }
+
+    /// Drops the account's event hub and dry-run state once the account is gone
+    pub fn forget(&self, account_id: &str) {
+        self.events.lock().unwrap().remove(account_id);
+        self.dry_runs.lock().unwrap().remove(account_id);
+    }
+
+    /// Returns the running bot's client if `account_id` is running
+    pub async fn get_client(&self, account_id: &str) -> Option<BotClient> {
+        self.bots.read().await.get(account_id).map(|b| b.client.clone())
+    }
+
+    /// Route command to the bot's gateway connection or to the REST api, the result is published
+    /// to the account's event subscribers
+    pub async fn send_command(&self, account_id: &str, command: BotCommand) -> ApiResult<()> {
+        self.run_command(account_id, command, None).await.map(|_| ())
+    }
+
+    /// [`BotManager::send_command`] returning the message a message command sent or edited,
+    /// `None` in dry-run mode. REST requests give up on rate limits longer than `max_retry_wait`
+    /// when it's set, for callers waiting on the result
+    pub async fn run_command(&self, account_id: &str, command: BotCommand, max_retry_wait: Option<Duration>) -> ApiResult<Option<Message>> {
+        // don't hold the lock during rest calls
+        let (mut client, commands) = match self.bots.read().await.get(account_id) {
+            None => return Err(ApiError::NotFound),
+            Some(bot) => (bot.client.clone(), bot.commands.clone())
+        };
+        if let Some(wait) = max_retry_wait {
+            client.api = client.api.with_max_retry_wait(wait);
+        }
+        let (transport, name, guild_id) = (command.transport(), command.name(), command.guild_id());
+        let result = Self::dispatch(&client, &commands, command).await;
+        client.events.publish(account_id, BotEventKind::CommandResult {
+            transport,
+            command: name,
+            guild_id,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+        result
+    }
+
+    async fn dispatch(client: &BotClient, commands: &Sender<BotCommand>, command: BotCommand) -> ApiResult<Option<Message>> {
+        match command.transport() {
+            CommandTransport::Rest => match command {
+                BotCommand::SetNickname(guild_id, nick) => client.set_nickname(guild_id, nick).await.map(|_| None),
+                BotCommand::SendMessage(channel_id, message) => client.send_message(channel_id, &message).await,
+                BotCommand::EditMessage(channel_id, message_id, edit) => client.edit_message(channel_id, message_id, &edit).await,
+                BotCommand::DeleteMessage(channel_id, message_id) => client.delete_message(channel_id, message_id).await.map(|_| None),
+                _ => unreachable!("only rest commands are routed here"),
+            },
+            CommandTransport::Gateway if !client.token_kind.has_gateway() => {
+                Err(ApiError::BadRequest(String::from("Bearer tokens can't send gateway commands")))
+            },
+            CommandTransport::Gateway if client.dry_run.intercept(&client.id, CommandTransport::Gateway, &command) => Ok(None),
+            CommandTransport::Gateway => commands.send(command).await.map(|_| None).map_err(|_| not_connected()),
+        }
+    }
+
+    /// Account's current voice connection in the guild, as seen by its gateway connection
+    pub async fn voice_state(&self, account_id: &str, guild_id: GuildId) -> ApiResult<Option<VoiceState>> {
+        match self.bots.read().await.get(account_id) {
+            Some(bot) if !bot.commands.is_closed() => Ok(bot.client.voice.get(guild_id, bot.client.account_id)),
+            _ => Err(not_connected())
+        }
+    }
+
+    /// Sends a voice command and waits for the gateway to confirm it, returning the resulting
+    /// voice state. In dry-run mode the state the bot would end up in is returned instead
+    pub async fn update_voice(&self, account_id: &str, command: BotCommand) -> ApiResult<VoiceState> {
+        let client = match self.bots.read().await.get(account_id) {
+            Some(bot) if !bot.commands.is_closed() => bot.client.clone(),
+            _ => return Err(not_connected())
+        };
+        if !client.token_kind.has_gateway() {
+            return Err(ApiError::BadRequest(String::from("Bearer tokens can't use voice")));
+        }
+        let guild_id = command.guild_id().ok_or_else(|| ApiError::BadRequest(String::from("Not a voice command")))?;
+        let current = client.voice.get(guild_id, client.account_id);
+        let not_in_voice = || ApiError::BadRequest(String::from("Not in a voice channel in this guild"));
+        let in_channel = current.as_ref().and_then(|state| state.channel_id);
+        let expected = match &command {
+            BotCommand::JoinChannel(_, channel_id) | BotCommand::MoveChannel(_, channel_id) => {
+                client.check_voice_access(guild_id, *channel_id).await?;
+                ExpectedVoice { channel_id: Some(*channel_id), self_mute: None, self_deaf: None }
+            },
+            BotCommand::LeaveChannel(..) => {
+                in_channel.ok_or_else(not_in_voice)?;
+                ExpectedVoice { channel_id: None, self_mute: None, self_deaf: None }
+            },
+            BotCommand::SetSelfMute(_, mute) => {
+                ExpectedVoice { channel_id: Some(in_channel.ok_or_else(not_in_voice)?), self_mute: Some(*mute), self_deaf: None }
+            },
+            BotCommand::SetSelfDeaf(_, deaf) => {
+                ExpectedVoice { channel_id: Some(in_channel.ok_or_else(not_in_voice)?), self_mute: None, self_deaf: Some(*deaf) }
+            },
+            _ => return Err(ApiError::BadRequest(String::from("Not a voice command")))
+        };
+
+        // subscribed before sending so the confirmation can't be missed
+        let (_, mut receiver) = client.events.subscribe();
+        self.send_command(account_id, command).await?;
+        if client.dry_run.is_active() {
+            let state = current.unwrap_or_else(|| VoiceState {
+                guild_id: Some(guild_id),
+                channel_id: None,
+                user_id: client.account_id,
+                session_id: String::new(),
+                deaf: false,
+                mute: false,
+                self_deaf: false,
+                self_mute: false,
+                self_stream: false,
+                self_video: false,
+                suppress: false,
+            });
+            return Ok(expected.apply(state));
+        }
+
+        let confirmed = time::timeout(VOICE_CONFIRM_TIMEOUT, async {
+            loop {
+                match receiver.recv().await {
+                    Ok(BotEvent { kind: BotEventKind::Gateway { event, guild_id: Some(event_guild), payload }, .. })
+                        if event == "VOICE_STATE_UPDATE" && event_guild == guild_id => {
+                        match serde_json::from_value::<VoiceState>(payload) {
+                            Ok(state) if state.user_id == client.account_id && expected.matches(&state) => return Ok(state),
+                            _ => {}
+                        }
+                    },
+                    Ok(BotEvent { kind: BotEventKind::ConnectionState(ConnectionState::Disconnected), .. }) |
+                    Err(RecvError::Closed) => return Err(not_connected()),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                }
+            }
+        }).await;
+        confirmed.unwrap_or_else(|_| Err(ApiError::Custom(
+            StatusCode::GATEWAY_TIMEOUT,
+            String::from("Discord didn't confirm the voice state change in time"),
+            None,
+        )))
+    }
+
+    /// Sends `command` to every account, gateway commands are queued on each bot's connection
+    /// and go out as its gateway rate limit allows
+    pub async fn send_group_command(&self, account_ids: Vec<String>, command: BotCommand) -> Vec<CommandResult> {
+        join_all(account_ids.into_iter().map(|account_id| {
+            let command = command.clone();
+            async move {
+                let result = self.send_command(&account_id, command).await;
+                CommandResult {
+                    account_id,
+                    success: result.is_ok(),
+                    error: result.err().map(|e| e.to_string()),
+                }
+            }
+        })).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discord_api::snowflake::{Snowflake, UserId};
+    use super::*;
+
+    fn voice_state(channel_id: Option<u64>, self_mute: bool, self_deaf: bool) -> VoiceState {
+        VoiceState {
+            guild_id: Some(GuildId(Snowflake(1))),
+            channel_id: channel_id.map(|id| ChannelId(Snowflake(id))),
+            user_id: UserId(Snowflake(2)),
+            session_id: String::new(),
+            deaf: false,
+            mute: false,
+            self_deaf,
+            self_mute,
+            self_stream: false,
+            self_video: false,
+            suppress: false,
         }
     }
 
-    pub async fn new_bot(&self, new_client: BotClient) {
-        let s = new_client.spawn_ws_conn().await;
-        self.bots.write().await.push((None, new_client))
+    #[test]
+    fn matches_channel() {
+        let expected = ExpectedVoice { channel_id: Some(ChannelId(Snowflake(3))), self_mute: None, self_deaf: None };
+        assert!(expected.matches(&voice_state(Some(3), true, false)));
+        assert!(!expected.matches(&voice_state(Some(4), true, false)));
+        assert!(!expected.matches(&voice_state(None, true, false)));
+
+        let left = ExpectedVoice { channel_id: None, self_mute: None, self_deaf: None };
+        assert!(left.matches(&voice_state(None, false, false)));
+        assert!(!left.matches(&voice_state(Some(3), false, false)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn matches_mute_and_deaf() {
+        let expected = ExpectedVoice { channel_id: Some(ChannelId(Snowflake(3))), self_mute: Some(true), self_deaf: None };
+        assert!(expected.matches(&voice_state(Some(3), true, true)));
+        assert!(!expected.matches(&voice_state(Some(3), false, true)));
+
+        let expected = ExpectedVoice { channel_id: Some(ChannelId(Snowflake(3))), self_mute: None, self_deaf: Some(false) };
+        assert!(expected.matches(&voice_state(Some(3), true, false)));
+        assert!(!expected.matches(&voice_state(Some(3), true, true)));
+    }
+
+    #[test]
+    fn apply_keeps_unset_fields() {
+        let expected = ExpectedVoice { channel_id: Some(ChannelId(Snowflake(3))), self_mute: Some(true), self_deaf: None };
+        let state = expected.apply(voice_state(None, false, true));
+        assert_eq!(state.channel_id, Some(ChannelId(Snowflake(3))));
+        assert!(state.self_mute);
+        assert!(state.self_deaf);
+        assert!(expected.matches(&state));
+    }
+}
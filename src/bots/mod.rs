@@ -1,4 +1,10 @@
 pub mod account_client;
-mod manager;
+pub mod manager;
 mod api_schema;
 mod ws;
+pub mod scheduler;
+pub mod reconciler;
+pub mod dry_run;
+pub mod events;
+pub mod token_health;
+pub mod voice;
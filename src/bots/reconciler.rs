@@ -0,0 +1,49 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use log::error;
+use tokio::time;
+use crate::api::err::{map_pool_err, ApiResult};
+use crate::bots::manager::BotManager;
+use crate::db::ConnPool;
+use crate::schemas::controlled_account::ControlledAccount;
+
+/// Used when `RECONCILE_INTERVAL_SECS` isn't set
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically compares `controlled_account` rows with the bots running in [`BotManager`] and
+/// fixes any drift. The first pass runs immediately, which starts every enabled account on boot.
+pub struct Reconciler {
+    pool: ConnPool,
+    bots: Arc<BotManager>,
+    interval: Duration,
+}
+
+impl Reconciler {
+    pub fn new(pool: ConnPool, bots: Arc<BotManager>) -> Self {
+        let interval = env::var("RECONCILE_INTERVAL_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_INTERVAL);
+        Self { pool, bots, interval }
+    }
+
+    pub async fn run(self) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reconcile().await {
+                error!("reconciliation failed: {e}");
+            }
+        }
+    }
+
+    async fn reconcile(&self) -> ApiResult<()> {
+        let accounts = {
+            let mut conn = self.pool.get().await.map_err(map_pool_err)?;
+            ControlledAccount::list_all(&mut conn).await?
+        };
+        self.bots.reconcile(&accounts).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use log::{error, info};
+use serde_json::json;
+use tokio::time;
+use crate::api::audit::AuditLog;
+use crate::api::err::{map_pool_err, ApiError, ApiResult};
+use crate::bots::manager::BotManager;
+use crate::db::ConnPool;
+use crate::schemas::audit_log::AuditEntry;
+use crate::schemas::scheduled_job::{JobRun, ScheduledJob};
+
+/// How often the job table is polled for due jobs
+const TICK: Duration = Duration::from_secs(5);
+
+/// Runs [`ScheduledJob`]s through the [`BotManager`]. All state lives in the database so jobs
+/// missed while the server was down run once on the first tick after a restart.
+pub struct Scheduler {
+    pool: ConnPool,
+    bots: Arc<BotManager>,
+    audit: AuditLog,
+}
+
+impl Scheduler {
+    pub fn new(pool: ConnPool, bots: Arc<BotManager>) -> Self {
+        Self { audit: AuditLog::new(pool.clone()), pool, bots }
+    }
+
+    pub async fn run(self) {
+        let mut interval = time::interval(TICK);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_due().await {
+                error!("scheduler tick failed: {e}");
+            }
+        }
+    }
+
+    async fn run_due(&self) -> ApiResult<()> {
+        let mut conn = self.pool.get().await.map_err(map_pool_err)?;
+        let now = Utc::now();
+        for mut job in ScheduledJob::get_due(now, &mut conn).await? {
+            // advanced before the command is sent, a job whose next run can't be stored is
+            // skipped until it can rather than sent again on every tick
+            if let Err(e) = job.advance(now, &mut conn).await {
+                error!("couldn't advance job {}, skipping it: {e}", job.id);
+                continue;
+            }
+            let result = match job.command() {
+                Err(e) => Err(ApiError::BadRequest(format!("Stored command is invalid: {e}"))),
+                Ok(command) => {
+                    let entry = AuditEntry::command(job.created_by.clone(), job.account_id.clone(), &command)
+                        .detail("job_id", json!(job.id));
+                    self.audit.around(entry, self.bots.send_command(&job.account_id, command)).await
+                }
+            };
+            if let Err(e) = &result {
+                error!("job {} failed: {e}", job.id);
+            } else {
+                info!("ran job {}", job.id);
+            }
+            // failing to store one job's outcome mustn't keep the rest from running
+            if let Err(e) = JobRun::new(&job, now, &result).create(&mut conn).await {
+                error!("couldn't record run of job {}: {e}", job.id);
+            }
+        }
+        Ok(())
+    }
+}
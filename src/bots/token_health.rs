@@ -0,0 +1,87 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use log::{error, info, warn};
+use tokio::time;
+use crate::api::DbConn;
+use crate::api::err::{map_pool_err, ApiError, ApiResult};
+use crate::bots::manager::BotManager;
+use crate::db::ConnPool;
+use crate::discord_api::DiscordClient;
+use crate::discord_api::errors::DiscordErrorCode;
+use crate::schemas::controlled_account::{ControlledAccount, TOKEN_INVALID};
+
+/// Used when `TOKEN_CHECK_INTERVAL_SECS` isn't set
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Periodically revalidates every controlled account's token against `/users/@me`. Accounts
+/// whose token Discord rejects are marked invalid and their bot is stopped, the reconciler
+/// won't start it again until a check passes.
+pub struct TokenHealthChecker {
+    pool: ConnPool,
+    bots: Arc<BotManager>,
+    interval: Duration,
+}
+
+/// Whether the error means the token itself is no good, as opposed to Discord or us failing
+fn is_token_rejected(err: &ApiError) -> bool {
+    match err {
+        ApiError::Discord(details) => details.status == 401 || details.code == DiscordErrorCode::InvalidToken,
+        _ => false,
+    }
+}
+
+impl TokenHealthChecker {
+    pub fn new(pool: ConnPool, bots: Arc<BotManager>) -> Self {
+        let interval = env::var("TOKEN_CHECK_INTERVAL_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_INTERVAL);
+        Self { pool, bots, interval }
+    }
+
+    pub async fn run(self) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.check_all().await {
+                error!("token health check failed: {e}");
+            }
+        }
+    }
+
+    async fn check_all(&self) -> ApiResult<()> {
+        let mut conn = self.pool.get().await.map_err(map_pool_err)?;
+        for mut account in ControlledAccount::list_all(&mut conn).await? {
+            if let Err(e) = self.check(&mut account, &mut conn).await {
+                error!("couldn't record token check for {}: {e}", account.id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn check(&self, account: &mut ControlledAccount, conn: &mut DbConn) -> ApiResult<()> {
+        let result = match DiscordClient::new(account.token(), account.token_kind()) {
+            Err(e) => Err(e),
+            Ok(api) => api.for_account(&account.id).get_current_user().await,
+        };
+        match result {
+            Ok(user) => {
+                if user.id != account.discord_id || user.username != account.username {
+                    info!("account {} is now {} ({})", account.id, user.username, user.id);
+                }
+                account.mark_token_valid(&user, conn).await
+            },
+            Err(e) if is_token_rejected(&e) => {
+                warn!("token of account {} was rejected: {e}", account.id);
+                account.mark_token_check_failed(Some(TOKEN_INVALID), e.to_string(), conn).await?;
+                self.bots.stop(&account.id).await;
+                Ok(())
+            },
+            Err(e) => {
+                warn!("couldn't check token of account {}: {e}", account.id);
+                account.mark_token_check_failed(None, e.to_string(), conn).await
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::discord_api::models::VoiceState;
+use crate::discord_api::snowflake::{ChannelId, GuildId, UserId};
+
+/// Who is connected to voice in the guilds a bot is in, kept up to date from its gateway
+/// connection
+#[derive(Debug, Default)]
+pub struct VoiceStates {
+    guilds: Mutex<HashMap<GuildId, HashMap<UserId, VoiceState>>>,
+}
+
+impl VoiceStates {
+    /// Replaces the guild's states with the ones sent in ready or guild create
+    pub fn load_guild(&self, guild_id: GuildId, states: Vec<VoiceState>) {
+        let states = states.into_iter()
+            .filter(|state| state.channel_id.is_some())
+            .map(|state| (state.user_id, VoiceState { guild_id: Some(guild_id), ..state }))
+            .collect();
+        self.guilds.lock().unwrap().insert(guild_id, states);
+    }
+
+    pub fn update(&self, state: VoiceState) {
+        let Some(guild_id) = state.guild_id else {
+            return;
+        };
+        let mut guilds = self.guilds.lock().unwrap();
+        let guild = guilds.entry(guild_id).or_default();
+        if state.channel_id.is_none() {
+            guild.remove(&state.user_id);
+        } else {
+            guild.insert(state.user_id, state);
+        }
+    }
+
+    /// Forgets everything, states are resent when the connection is identified again
+    pub fn clear(&self) {
+        self.guilds.lock().unwrap().clear();
+    }
+
+    pub fn get(&self, guild_id: GuildId, user_id: UserId) -> Option<VoiceState> {
+        self.guilds.lock().unwrap().get(&guild_id).and_then(|guild| guild.get(&user_id)).cloned()
+    }
+
+    /// Number of users connected to the channel
+    pub fn occupants(&self, guild_id: GuildId, channel_id: ChannelId) -> usize {
+        self.guilds.lock().unwrap().get(&guild_id)
+            .map(|guild| guild.values().filter(|state| state.channel_id == Some(channel_id)).count())
+            .unwrap_or(0)
+    }
+}
@@ -1,110 +1,256 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::sync::{Arc, Once};
 use std::sync::atomic::{AtomicI32, Ordering};
-use std::thread;
 use std::time::Duration;
 use async_channel::{Receiver, Sender, unbounded};
 use async_tungstenite::tokio::{connect_async, ConnectStream};
 use async_tungstenite::tungstenite::Message;
 use async_tungstenite::{tungstenite, WebSocketStream};
-use futures_util::{future, SinkExt, StreamExt};
+use futures_util::{SinkExt, StreamExt};
 use futures_util::stream::SplitStream;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use rand::Rng;
 use tokio::runtime::Handle;
-use tokio::sync::{Mutex, OnceCell};
+use tokio::sync::Mutex;
 use tokio::time;
+use tokio::time::Instant;
 use crate::bots::api_schema::{IncomingWsEvent, WsMessage, WsMessageType};
+use crate::bots::events::{BotEventKind, ConnectionState, EventSink};
+use crate::bots::account_client::BotClient;
 use crate::bots::manager::BotCommand;
+use crate::bots::voice::VoiceStates;
+use crate::discord_api::gateway::{gateway_url, IdentifyOptions, GATEWAY_QUERY};
+use crate::discord_api::models::VoiceState;
+use crate::discord_api::snowflake::{ChannelId, GuildId};
+use crate::discord_api::token::TokenKind;
 
-pub fn ws_loop(token: String, sess_id: Arc<Mutex<Option<String>>>, recv: Arc<Receiver<BotCommand>>) -> impl Future<Output=()> {
-    async move {
-        let once_heartbeat = Arc::new(Once::new());
-        let handle = Handle::current();
-        let (ws, _r) = match connect_async("wss://gateway.discord.gg/?v=10&encoding=json").await {
-            Err(e) => {
-                error!("{}", e);
-                return;
-            },
-            Ok(ws) => ws
-        };
-        let (write, mut read) = init_ws_conn(token, ws).await;
-        handle.spawn(async move {
-            let last_ack = Arc::new(AtomicI32::new(-1));
-
-            while let Some(item) = read.next().await {
-                match on_incoming_msg(item, sess_id.clone(), once_heartbeat.clone(), write.clone(), last_ack.clone()).await {
-                    Err((e, str_version)) => {
-                        info!("{}", str_version);
-                        error!("{e}");
-                    },
-                    _ => {}
-                };
+pub async fn ws_loop(client: BotClient, sess_id: Arc<Mutex<Option<String>>>, recv: Arc<Receiver<BotCommand>>) {
+    let once_heartbeat = Arc::new(Once::new());
+    let handle = Handle::current();
+    let events = EventSink { account_id: client.id.clone(), hub: client.events.clone() };
+    let voice = client.voice.clone();
+    // states are resent by ready and guild create
+    voice.clear();
+    events.publish(BotEventKind::ConnectionState(ConnectionState::Connecting));
+    let url = format!("{}/{}", gateway_url(&client.api, client.token_kind).await.trim_end_matches('/'), GATEWAY_QUERY);
+    let (ws, _r) = match connect_async(url).await {
+        Err(e) => {
+            error!("{}", e);
+            events.publish(BotEventKind::ConnectionState(ConnectionState::Disconnected));
+            return;
+        },
+        Ok(ws) => ws
+    };
+    let (write, mut read) = init_ws_conn(client.account_token, client.token_kind, client.identify, ws).await;
+    handle.spawn(forward_commands(recv.clone(), write.clone()));
+    handle.spawn(async move {
+        let last_ack = Arc::new(AtomicI32::new(-1));
+
+        while let Some(item) = read.next().await {
+            if let Err((e, str_version)) = on_incoming_msg(item, &events, &voice, sess_id.clone(), once_heartbeat.clone(), write.clone(), last_ack.clone()).await {
+                info!("{}", str_version);
+                error!("{e}");
             }
-            info!("over");
-        });
+        }
+        info!("over");
+        events.publish(BotEventKind::ConnectionState(ConnectionState::Disconnected));
+        // lets the manager know this connection is gone
+        recv.close();
+        write.close();
+    });
 
-    }
 }
 
-async fn on_incoming_msg(item: Result<Message, tungstenite::Error>, sess_id: Arc<Mutex<Option<String>>>, once_heartbeat: Arc<Once>, ws_sender: Sender<WsMessageType>, last_ack: Arc<AtomicI32>) -> anyhow::Result<(), (anyhow::Error, String)> {
+async fn on_incoming_msg(item: Result<Message, tungstenite::Error>, events: &EventSink, voice: &VoiceStates, sess_id: Arc<Mutex<Option<String>>>, once_heartbeat: Arc<Once>, ws_sender: Sender<WsMessageType>, last_ack: Arc<AtomicI32>) -> anyhow::Result<(), (anyhow::Error, String)> {
     let mut str_version = String::new();
     async  {
         str_version = item?.into_text()?;
         let msg = serde_json::from_str::<WsMessage>(str_version.as_str())?;
-        match msg.s {
-            Some(s) => {
-                Arc::clone(&last_ack).swap(s, Ordering::Relaxed);
-            },
-            None => {}
+        if let Some(s) = msg.s {
+            Arc::clone(&last_ack).swap(s, Ordering::Relaxed);
         }
-        match msg.d {
-            Some(data) => {
-                let incoming = match serde_json::from_value::<IncomingWsEvent>(data.clone()) {
-                    Err(_e) => {
-
-                        let msg_type = msg.t.unwrap_or(String::from("none"));
-                        if vec![String::from("SESSIONS_REPLACE"), String::from("PRESENCE_UPDATE")].contains(&msg_type) {
-                            info!("{}", data);
-                        }
-                        info!("message type ({}) ({}) not implemented", msg_type, msg.op);
-                        return Ok(())
-                    },
-                    Ok(v) => v
-                };
-                match incoming {
-                    IncomingWsEvent::Hello {heartbeat_interval} => {
-                        let handle = Handle::current();
-                        once_heartbeat.call_once(move || {
-                            handle.spawn(spawn_heartbeat(ws_sender.clone(), Arc::clone(&last_ack), heartbeat_interval as u64));
-                        });
-                    },
-                    IncomingWsEvent::Ready {
-                        v: _v,
-                        user: _u,
-                        guilds,
-                        session_id,
-                        resume_gateway_url: _r,
-                        shard: _sh,
-                    } => {
-                        let sid = session_id.clone();
-                        let binding = sess_id.clone();
-                        let mut s_id_lock = binding.lock().await;
-                        let _ = s_id_lock.insert(sid);
-                        info!("{}", serde_json::to_string_pretty(&guilds[2].voice_states)?);
-                    },
-                    _ => {
-                        info!("{incoming:?}");
+        if let Some(data) = msg.d {
+            match msg.t.as_deref() {
+                // ready is reported as a connection state, its payload is too large to stream
+                Some("READY") => events.publish(BotEventKind::ConnectionState(ConnectionState::Ready)),
+                Some(event) if msg.op == 0 => events.publish(BotEventKind::Gateway {
+                    event: event.to_string(),
+                    guild_id: data.get("guild_id").and_then(|g| serde_json::from_value(g.clone()).ok()),
+                    payload: data.clone(),
+                }),
+                _ => {}
+            }
+            update_voice_states(voice, msg.t.as_deref(), &data);
+            let incoming = match serde_json::from_value::<IncomingWsEvent>(data.clone()) {
+                Err(_e) => {
+
+                    let msg_type = msg.t.unwrap_or(String::from("none"));
+                    if [String::from("SESSIONS_REPLACE"), String::from("PRESENCE_UPDATE")].contains(&msg_type) {
+                        info!("{}", data);
                     }
+                    info!("message type ({}) ({}) not implemented", msg_type, msg.op);
+                    return Ok(())
+                },
+                Ok(v) => v
+            };
+            match incoming {
+                IncomingWsEvent::Hello {heartbeat_interval} => {
+                    let handle = Handle::current();
+                    once_heartbeat.call_once(move || {
+                        handle.spawn(spawn_heartbeat(ws_sender.clone(), Arc::clone(&last_ack), heartbeat_interval as u64));
+                    });
+                },
+                IncomingWsEvent::Ready {
+                    v: _v,
+                    user: _u,
+                    guilds,
+                    session_id,
+                    resume_gateway_url: _r,
+                    shard: _sh,
+                } => {
+                    let sid = session_id.clone();
+                    let binding = sess_id.clone();
+                    let mut s_id_lock = binding.lock().await;
+                    let _ = s_id_lock.insert(sid);
+                    info!("ready in {} guilds", guilds.len());
+                },
+                _ => {
+                    info!("{incoming:?}");
                 }
-            },
-            None => {}
+            }
         }
         Ok::<(), anyhow::Error>(())
     }.await.map_err(|e| (e, str_version))
 
 }
 
+/// Feeds voice states from ready, guild create and voice state update dispatches into the cache
+fn update_voice_states(voice: &VoiceStates, event: Option<&str>, data: &serde_json::Value) {
+    let guild_states = |guild: &serde_json::Value| -> Option<(GuildId, Vec<VoiceState>)> {
+        let guild_id = serde_json::from_value(guild.get("id")?.clone()).ok()?;
+        let states = serde_json::from_value(guild.get("voice_states")?.clone()).ok()?;
+        Some((guild_id, states))
+    };
+    match event {
+        Some("READY") => {
+            let guilds = data.get("guilds").and_then(|g| g.as_array()).cloned().unwrap_or_default();
+            for (guild_id, states) in guilds.iter().filter_map(guild_states) {
+                voice.load_guild(guild_id, states);
+            }
+        },
+        Some("GUILD_CREATE") => {
+            if let Some((guild_id, states)) = guild_states(data) {
+                voice.load_guild(guild_id, states);
+            }
+        },
+        Some("VOICE_STATE_UPDATE") => match serde_json::from_value::<VoiceState>(data.clone()) {
+            Err(e) => warn!("couldn't parse voice state: {e}"),
+            Ok(state) => voice.update(state),
+        },
+        _ => {}
+    }
+}
+
+/// Voice state the bot last asked for in a guild
+#[derive(Clone, Debug, Default)]
+struct OwnVoiceState {
+    channel_id: Option<ChannelId>,
+    self_mute: bool,
+    self_deaf: bool,
+}
+
+impl OwnVoiceState {
+    fn to_ws_message(&self, guild_id: GuildId) -> WsMessageType {
+        WsMessageType::UpdateVoiceState {
+            guild_id,
+            channel_id: self.channel_id,
+            self_mute: self.self_mute,
+            self_deaf: self.self_deaf,
+        }
+    }
+}
+
+/// Discord allows 120 gateway events per connection every 60 seconds, commands get a bit less
+/// so heartbeats and identify are never starved
+const COMMAND_RATE_LIMIT: u32 = 110;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Fixed window limiter for commands sent over one gateway connection
+struct GatewayRateLimiter {
+    window_start: Instant,
+    sent: u32,
+}
+
+impl GatewayRateLimiter {
+    fn new() -> Self {
+        Self { window_start: Instant::now(), sent: 0 }
+    }
+
+    /// Waits until another command can be sent
+    async fn acquire(&mut self) {
+        if self.window_start.elapsed() >= RATE_LIMIT_WINDOW {
+            self.window_start = Instant::now();
+            self.sent = 0;
+        }
+        if self.sent >= COMMAND_RATE_LIMIT {
+            warn!("gateway command rate limit reached, waiting for next window");
+            time::sleep_until(self.window_start + RATE_LIMIT_WINDOW).await;
+            self.window_start = Instant::now();
+            self.sent = 0;
+        }
+        self.sent += 1;
+    }
+}
+
+/// Translates [`BotCommand`]s into gateway messages until either channel closes
+async fn forward_commands(recv: Arc<Receiver<BotCommand>>, write_chan: Sender<WsMessageType>) {
+    let mut voice_states = HashMap::<GuildId, OwnVoiceState>::new();
+    let mut rate_limiter = GatewayRateLimiter::new();
+    while let Ok(command) = recv.recv().await {
+        let msg = match command {
+            BotCommand::JoinChannel(guild_id, channel_id) | BotCommand::MoveChannel(guild_id, channel_id) => {
+                let state = voice_states.entry(guild_id).or_default();
+                state.channel_id = Some(channel_id);
+                state.to_ws_message(guild_id)
+            },
+            BotCommand::LeaveChannel(guild_id, _channel_id) => {
+                let state = voice_states.remove(&guild_id).unwrap_or_default();
+                OwnVoiceState { channel_id: None, ..state }.to_ws_message(guild_id)
+            },
+            BotCommand::SetSelfMute(guild_id, self_mute) => {
+                let state = voice_states.entry(guild_id).or_default();
+                state.self_mute = self_mute;
+                state.to_ws_message(guild_id)
+            },
+            BotCommand::SetSelfDeaf(guild_id, self_deaf) => {
+                let state = voice_states.entry(guild_id).or_default();
+                state.self_deaf = self_deaf;
+                state.to_ws_message(guild_id)
+            },
+            BotCommand::SetPresence { status, activities, afk } => WsMessageType::UpdatePresence {
+                since: None,
+                activities,
+                status,
+                afk,
+            },
+            BotCommand::Disconnect => WsMessageType::InternalDisconnect,
+            BotCommand::SetNickname(..) |
+            BotCommand::SendMessage(..) |
+            BotCommand::EditMessage(..) |
+            BotCommand::DeleteMessage(..) => {
+                warn!("rest command sent to gateway connection, dropping it");
+                continue;
+            }
+        };
+        rate_limiter.acquire().await;
+        if write_chan.send(msg).await.is_err() {
+            break;
+        }
+    }
+    recv.close();
+}
+
 fn spawn_heartbeat(write_chan: Sender<WsMessageType>, last_ack: Arc<AtomicI32>, heartbeat_interval: u64) -> impl Future<Output=()> {
     let mut thread_rng= rand::thread_rng();
     let random_sleep = thread_rng.gen_range(0..heartbeat_interval);
@@ -118,17 +264,14 @@ fn spawn_heartbeat(write_chan: Sender<WsMessageType>, last_ack: Arc<AtomicI32>,
             } else {
                 Some(last_ack.load(Ordering::Relaxed))
             };
-            match write_chan.send(WsMessageType::Heartbeat(last_ack)).await {
-                Err(_e) => {
-                    break;
-                },
-                Ok(()) => {}
-            };
+            if write_chan.send(WsMessageType::Heartbeat(last_ack)).await.is_err() {
+                break;
+            }
         }
     }
 }
 
-async fn init_ws_conn(token: String, ws: WebSocketStream<ConnectStream>) -> (Sender<WsMessageType>, SplitStream<WebSocketStream<ConnectStream>>) {
+async fn init_ws_conn(token: String, token_kind: TokenKind, options: IdentifyOptions, ws: WebSocketStream<ConnectStream>) -> (Sender<WsMessageType>, SplitStream<WebSocketStream<ConnectStream>>) {
     let (mut write, read) = ws.split();
     let (write_s, write_r) = unbounded::<WsMessageType>();
     let handle = Handle::current();
@@ -137,44 +280,57 @@ async fn init_ws_conn(token: String, ws: WebSocketStream<ConnectStream>) -> (Sen
             match write_r.recv().await {
                 Err(_) | Ok(WsMessageType::InternalDisconnect) => {
                     write_r.close();
+                    if let Err(e) = write.close().await {
+                        error!("{e}");
+                    }
                     return;
                 },
                 Ok(v) => {
-                    let str_msg = match serde_json::to_string(&v.into_ws_message()) {
+                    let msg = v.into_ws_message();
+                    let str_msg = match serde_json::to_string(&msg) {
                         Err(e) => {
                             error!("{e}");
                             continue;
                         },
                         Ok(v) => v
                     };
-                    info!("{str_msg}");
-                    match write.send(Message::Text(str_msg)).await {
-                        Err(e) => {
-                            match e {
-                                tungstenite::Error::ConnectionClosed |
-                                tungstenite::Error::AlreadyClosed => {
-                                    write_r.close();
-                                    return;
-                                },
-                                _ => {
-                                    error!("{e}");
-                                }
+                    // only the opcode, identify and resume payloads carry the token
+                    debug!("sending gateway op {}", msg.op);
+                    if let Err(e) = write.send(Message::Text(str_msg)).await {
+                        match e {
+                            tungstenite::Error::ConnectionClosed |
+                            tungstenite::Error::AlreadyClosed => {
+                                write_r.close();
+                                return;
+                            },
+                            _ => {
+                                error!("{e}");
                             }
                         }
-                        Ok(_) => {}
-                    };
+                    }
                 }
             }
         }
     });
     let inside_wrs = write_s.clone();
-    handle.spawn(async move {
-        inside_wrs.send(WsMessageType::Identify {
+    let identify = match token_kind {
+        TokenKind::Bot => WsMessageType::Identify {
             token,
-            os: String::from("win"),
-            browser: String::from("disco"),
-            device: String::from("disco"),
-        }).await
+            os: options.os.unwrap_or_else(|| String::from(std::env::consts::OS)),
+            browser: options.browser.unwrap_or_else(|| String::from("feeble-bot")),
+            device: options.device.unwrap_or_else(|| String::from("feeble-bot")),
+            intents: token_kind.intents(),
+        },
+        _ => WsMessageType::Identify {
+            token,
+            os: options.os.unwrap_or_else(|| String::from("win")),
+            browser: options.browser.unwrap_or_else(|| String::from("disco")),
+            device: options.device.unwrap_or_else(|| String::from("disco")),
+            intents: None,
+        },
+    };
+    handle.spawn(async move {
+        inside_wrs.send(identify).await
     });
 
     (write_s, read)
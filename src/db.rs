@@ -1,35 +1,55 @@
 use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
 use diesel_async::pooled_connection::deadpool::Pool;
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use log::warn;
 use rand::{random};
 use crate::schema::users::dsl::users;
 use crate::schemas::User;
+use crate::schemas::user::OWNER;
+use crate::PROD;
 
 pub type ConnPool = Pool<AsyncPgConnection>;
 
+/// Where the owner's generated password is written when `OWNER_PASSWORD` isn't set
+const OWNER_PASSWORD_FILE: &str = "owner_password.txt";
+
 /// Generates random 8 byte integer encod as hex
 pub fn gen_id() -> String {
     hex::encode(random::<[u8; 8]>())
 }
 
+/// Creates the owner account when there are no users yet, from `OWNER_USERNAME`, `OWNER_PASSWORD`
+/// and `OWNER_EMAIL`. Everyone else registers with an invite. `OWNER_PASSWORD` is required in
+/// production, elsewhere a generated one is written to [`OWNER_PASSWORD_FILE`], readable only by
+/// the user running the server
 pub async fn init_db(pool: ConnPool) -> anyhow::Result<()> {
     let mut conn = pool.get().await?;
     match users.first::<User>(&mut conn).await {
-        Err(e) => {
-            match e {
-                diesel::result::Error::NotFound => {
-                    let u = User::new(String::from("cchosch"), String::from("admin"), String::new());
-                    diesel::insert_into(users).values(&u).execute(&mut conn).await?;
-                    return Ok(())
-                },
-                _ => {
-                    Err(e)?
-                }
-            }
-        },
-        Ok(_u) => {return Ok(())}
+        Err(diesel::result::Error::NotFound) => {},
+        Err(e) => Err(e)?,
+        Ok(_u) => return Ok(())
     }
+    let username = env::var("OWNER_USERNAME").unwrap_or(String::from("cchosch"));
+    let password = match env::var("OWNER_PASSWORD") {
+        Ok(password) => password,
+        Err(_) if PROD => anyhow::bail!("OWNER_PASSWORD must be set to create the owner account"),
+        Err(_) => {
+            let password = hex::encode(random::<[u8; 12]>());
+            // written before the owner exists so the password can't be lost, a failed run's file
+            // is replaced by the next one
+            let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(OWNER_PASSWORD_FILE)?;
+            writeln!(file, "{password}")?;
+            warn!("OWNER_PASSWORD isn't set, creating owner {username} with the password written to {OWNER_PASSWORD_FILE}");
+            password
+        }
+    };
+    let mut u = User::new(username, password, env::var("OWNER_EMAIL").unwrap_or_default());
+    u.set_flag(OWNER, true);
+    diesel::insert_into(users).values(&u).execute(&mut conn).await?;
     Ok(())
 }
 
@@ -40,8 +60,6 @@ pub(crate) fn gen_pool() -> ConnPool {
     let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(db_url);
     // Refer to the `r2d2` documentation for more methods to use
     // when building a connection pool
-    let pool =
-        Pool::builder(manager).build().unwrap();
-    pool
+    Pool::builder(manager).build().unwrap()
 }
 
@@ -1,10 +1,43 @@
+pub mod errors;
+pub mod gateway;
+pub mod models;
+pub mod permissions;
+pub mod ratelimit;
+pub mod retry;
+pub mod snowflake;
+pub mod token;
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use axum::http::StatusCode;
+use log::{debug, error, warn};
+use reqwest::{Method, RequestBuilder};
+use reqwest::multipart::{Form, Part};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use crate::BASE_URL;
+use crate::api::err::{ApiError, ApiResult};
+use crate::discord_api::errors::{DiscordErrorCode, DiscordErrorDetails, flatten_errors};
+use crate::discord_api::gateway::{GatewayBotInfo, GatewayInfo};
+use crate::discord_api::models::{Channel, DiscordUser, EditMessage, FileUpload, Guild, GuildMember, Message, ModifyCurrentUser, OutgoingMessage, PartialGuild, VoiceRegion};
+use crate::discord_api::ratelimit::{RateLimited, RateLimiter};
+use crate::discord_api::retry::{request_timeout, RetryPolicy};
+use crate::discord_api::snowflake::{ChannelId, GuildId, MessageId, UserId};
+use crate::discord_api::token::TokenKind;
+
+/// 429s a single request waits out before giving up, only for clients with a
+/// [`DiscordClient::with_max_retry_wait`]
+const MAX_RATE_LIMITED_ATTEMPTS: u32 = 5;
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct DiscordApiError {
-    pub code: i64,
+    pub code: DiscordErrorCode,
     pub message: String,
+    /// Nested validation errors, see [`flatten_errors`]
+    #[serde(default)]
     pub errors: Value
 }
 
@@ -23,3 +56,262 @@ impl<T> DiscordApiResponse<T> {
         }
     }
 }
+
+impl DiscordApiError {
+    /// Converts error Discord answered with http `status` into the error our api responds with
+    pub fn into_api_error(self, status: u16) -> ApiError {
+        ApiError::Discord(DiscordErrorDetails {
+            status,
+            code: self.code,
+            errors: flatten_errors(&self.errors),
+            message: self.message,
+        })
+    }
+}
+
+fn map_err_invalid(e: impl std::error::Error) -> ApiError {
+    error!("{e}");
+    ApiError::InternalError
+}
+
+/// [`map_err_invalid`] for errors of a sent request, they print the url which holds the token on
+/// webhook routes
+fn map_request_err(e: reqwest::Error) -> ApiError {
+    map_err_invalid(e.without_url())
+}
+
+/// Route with webhook tokens cut out so it can be logged
+pub(crate) fn loggable_route(route: &str) -> String {
+    match route.strip_prefix("/webhooks/").and_then(|rest| rest.split_once('/')) {
+        None => route.to_string(),
+        Some((id, _)) => format!("/webhooks/{}/***", id),
+    }
+}
+
+fn file_part(file: &FileUpload) -> ApiResult<Part> {
+    let part = Part::bytes(file.data.clone()).file_name(file.filename.clone());
+    match &file.content_type {
+        None => Ok(part),
+        Some(mime) => part.mime_str(mime).map_err(|_| ApiError::BadRequest(format!("Invalid content type {mime}"))),
+    }
+}
+
+/// Typed client for Discord's REST api, authenticated as a single account. Requests wait for
+/// the token's rate limits and are retried after every 429 unless the client has a longest wait
+#[derive(Clone, Debug)]
+pub struct DiscordClient {
+    http: reqwest::Client,
+    limiter: Arc<RateLimiter>,
+    /// Longest 429 `retry_after` waited out, longer ones are returned as errors. `None` waits
+    /// out every limit
+    max_retry_wait: Option<Duration>,
+    retry: RetryPolicy,
+    /// Controlled account the client acts for, only used in logs
+    account_id: String,
+}
+
+impl DiscordClient {
+    pub fn new(token: &str, kind: TokenKind) -> ApiResult<Self> {
+        let http = reqwest::ClientBuilder::new().user_agent(kind.user_agent()).timeout(request_timeout()).default_headers({
+            let mut dft_headers = HeaderMap::new();
+            dft_headers.insert(AUTHORIZATION, HeaderValue::from_str(&kind.authorization(token)).map_err(map_err_invalid)?);
+            dft_headers
+        }).build().map_err(map_err_invalid)?;
+        Ok(Self {
+            http,
+            limiter: RateLimiter::for_token(token),
+            max_retry_wait: None,
+            retry: RetryPolicy::default(),
+            account_id: String::from("-"),
+        })
+    }
+
+    /// Client without credentials, for routes like webhooks where the token is part of the url.
+    /// Every unauthenticated client shares one limiter, buckets are still split per webhook
+    pub fn unauthenticated() -> ApiResult<Self> {
+        let http = reqwest::ClientBuilder::new().user_agent(TokenKind::Bot.user_agent()).timeout(request_timeout()).build().map_err(map_err_invalid)?;
+        Ok(Self {
+            http,
+            limiter: RateLimiter::for_token(""),
+            max_retry_wait: None,
+            retry: RetryPolicy::default(),
+            account_id: String::from("webhook"),
+        })
+    }
+
+    /// Tags the client's logs with the controlled account it acts for
+    pub fn for_account(mut self, account_id: &str) -> Self {
+        self.account_id = account_id.to_string();
+        self
+    }
+
+    /// Return rate limit errors when Discord asks to wait longer than `wait` or after
+    /// [`MAX_RATE_LIMITED_ATTEMPTS`] 429s instead of waiting them all out
+    pub fn with_max_retry_wait(mut self, wait: Duration) -> Self {
+        self.max_retry_wait = Some(wait);
+        self
+    }
+
+    /// Sends request to `route` (relative to the api base url) with a json body and decodes the
+    /// response
+    pub(crate) async fn request<T: DeserializeOwned>(&self, method: Method, route: &str, body: Option<&(impl Serialize + ?Sized)>) -> ApiResult<T> {
+        self.send(method, route, |req| Ok(match body {
+            None => req,
+            Some(body) => req.json(body),
+        })).await
+    }
+
+    /// Sends request to `route`, `build` adds the body and is called again for every retry.
+    /// Transient failures of idempotent requests are retried following [`RetryPolicy`]
+    async fn send<T: DeserializeOwned>(&self, method: Method, route: &str, build: impl Fn(RequestBuilder) -> ApiResult<RequestBuilder>) -> ApiResult<T> {
+        let log_route = loggable_route(route);
+        let mut attempt = 1;
+        let mut rate_limited = 0;
+        let resp = loop {
+            self.limiter.acquire(&method, route).await;
+            let req = build(self.http.request(method.clone(), format!("{}{}", BASE_URL, route)))?;
+            debug!("[{}] {method} {log_route} attempt {attempt}", self.account_id);
+            // the url is left out of errors since it can hold a webhook token, logs use `log_route`
+            let result = req.send().await.map_err(reqwest::Error::without_url);
+            if self.retry.should_retry(&method, attempt, &result) {
+                let delay = self.retry.backoff(attempt);
+                let reason = match &result {
+                    Ok(resp) => resp.status().to_string(),
+                    Err(e) => e.to_string(),
+                };
+                warn!("[{}] {method} {log_route} attempt {attempt} failed ({reason}), retrying in {delay:?}", self.account_id);
+                time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            let resp = result.map_err(|e| {
+                error!("[{}] {method} {log_route} attempt {attempt} failed: {e}", self.account_id);
+                ApiError::InternalError
+            })?;
+            self.limiter.update(&method, route, resp.headers());
+            if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                break resp;
+            }
+
+            let limited = resp.json::<RateLimited>().await.map_err(map_request_err)?;
+            self.limiter.rate_limited(&method, route, &limited);
+            rate_limited += 1;
+            let give_up = self.max_retry_wait.is_some_and(|max_wait| {
+                rate_limited >= MAX_RATE_LIMITED_ATTEMPTS || limited.retry_after > max_wait.as_secs_f64()
+            });
+            if give_up {
+                return Err(ApiError::Custom(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    limited.message,
+                    Some(format!("Retry after {:.1} seconds", limited.retry_after))
+                ));
+            }
+        };
+        let status = resp.status();
+        let bytes = resp.bytes().await.map_err(map_request_err)?;
+        // 204 responses have no body
+        let bytes = if bytes.is_empty() { b"null".as_slice().into() } else { bytes };
+        match serde_json::from_slice::<DiscordApiResponse<T>>(&bytes).map_err(map_err_invalid)?.into_result() {
+            Ok(data) if status.is_success() => Ok(data),
+            Ok(_) => {
+                error!("[{}] unexpected {status} response from {log_route}", self.account_id);
+                Err(ApiError::InternalError)
+            },
+            Err(err) => Err(err.into_api_error(status.as_u16()))
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, route: &str) -> ApiResult<T> {
+        self.request(Method::GET, route, None::<&()>).await
+    }
+
+    pub async fn get_current_user(&self) -> ApiResult<DiscordUser> {
+        self.get("/users/@me").await
+    }
+
+    pub async fn get_user(&self, user_id: UserId) -> ApiResult<DiscordUser> {
+        self.get(&format!("/users/{}", user_id)).await
+    }
+
+    pub async fn modify_current_user(&self, payload: &ModifyCurrentUser) -> ApiResult<DiscordUser> {
+        self.request(Method::PATCH, "/users/@me", Some(payload)).await
+    }
+
+    pub async fn get_current_user_guilds(&self) -> ApiResult<Vec<PartialGuild>> {
+        self.get("/users/@me/guilds").await
+    }
+
+    pub async fn get_guild(&self, guild_id: GuildId) -> ApiResult<Guild> {
+        self.get(&format!("/guilds/{}?with_counts=true", guild_id)).await
+    }
+
+    pub async fn get_guild_channels(&self, guild_id: GuildId) -> ApiResult<Vec<Channel>> {
+        self.get(&format!("/guilds/{}/channels", guild_id)).await
+    }
+
+    pub async fn get_guild_member(&self, guild_id: GuildId, user_id: UserId) -> ApiResult<GuildMember> {
+        self.get(&format!("/guilds/{}/members/{}", guild_id, user_id)).await
+    }
+
+    /// Members ordered by user id, `limit` is capped at 1000 by Discord
+    pub async fn list_guild_members(&self, guild_id: GuildId, limit: u32, after: Option<UserId>) -> ApiResult<Vec<GuildMember>> {
+        let mut route = format!("/guilds/{}/members?limit={}", guild_id, limit);
+        if let Some(after) = after {
+            route.push_str(&format!("&after={}", after));
+        }
+        self.get(&route).await
+    }
+
+    /// Set (or reset with `None`) the current user's nickname in a guild
+    pub async fn modify_current_member(&self, guild_id: GuildId, nick: Option<String>) -> ApiResult<GuildMember> {
+        self.request(Method::PATCH, &format!("/guilds/{}/members/@me", guild_id), Some(&serde_json::json!({ "nick": nick }))).await
+    }
+
+    pub async fn get_channel(&self, channel_id: ChannelId) -> ApiResult<Channel> {
+        self.get(&format!("/channels/{}", channel_id)).await
+    }
+
+    pub async fn get_gateway(&self) -> ApiResult<GatewayInfo> {
+        self.get("/gateway").await
+    }
+
+    /// Bot tokens only, includes the recommended shard count and identify limits
+    pub async fn get_gateway_bot(&self) -> ApiResult<GatewayBotInfo> {
+        self.get("/gateway/bot").await
+    }
+
+    pub async fn list_voice_regions(&self) -> ApiResult<Vec<VoiceRegion>> {
+        self.get("/voice/regions").await
+    }
+
+    /// Posts message, files are sent as multipart with the message itself in `payload_json`
+    pub async fn create_message(&self, channel_id: ChannelId, message: &OutgoingMessage) -> ApiResult<Message> {
+        let route = format!("/channels/{}/messages", channel_id);
+        if message.files.is_empty() {
+            return self.request(Method::POST, &route, Some(&message.message)).await;
+        }
+
+        let mut payload = serde_json::to_value(&message.message).map_err(map_err_invalid)?;
+        payload["attachments"] = message.files.iter().enumerate().map(|(i, file)| serde_json::json!({
+            "id": i,
+            "filename": file.filename,
+            "description": file.description,
+        })).collect();
+        let payload = payload.to_string();
+        self.send(Method::POST, &route, |req| {
+            let mut form = Form::new().text("payload_json", payload.clone());
+            for (i, file) in message.files.iter().enumerate() {
+                form = form.part(format!("files[{}]", i), file_part(file)?);
+            }
+            Ok(req.multipart(form))
+        }).await
+    }
+
+    pub async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, message: &EditMessage) -> ApiResult<Message> {
+        self.request(Method::PATCH, &format!("/channels/{}/messages/{}", channel_id, message_id), Some(message)).await
+    }
+
+    pub async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> ApiResult<()> {
+        self.request(Method::DELETE, &format!("/channels/{}/messages/{}", channel_id, message_id), None::<&()>).await
+    }
+}
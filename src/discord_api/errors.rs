@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Discord's JSON error codes, codes we don't handle specially are kept as `Other`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum DiscordErrorCode {
+    General,
+    UnknownAccount,
+    UnknownChannel,
+    UnknownGuild,
+    UnknownMember,
+    UnknownRole,
+    UnknownUser,
+    UnknownVoiceState,
+    BotsCannotUseEndpoint,
+    OnlyBotsCanUseEndpoint,
+    MaxGuildsReached,
+    Unauthorized,
+    VerificationRequired,
+    MissingAccess,
+    InvalidAccountType,
+    CannotExecuteOnDm,
+    MissingPermissions,
+    InvalidToken,
+    InvalidFormBody,
+    InvalidJson,
+    Other(i64),
+}
+
+impl From<i64> for DiscordErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            0 => DiscordErrorCode::General,
+            10001 => DiscordErrorCode::UnknownAccount,
+            10003 => DiscordErrorCode::UnknownChannel,
+            10004 => DiscordErrorCode::UnknownGuild,
+            10007 => DiscordErrorCode::UnknownMember,
+            10011 => DiscordErrorCode::UnknownRole,
+            10013 => DiscordErrorCode::UnknownUser,
+            10065 => DiscordErrorCode::UnknownVoiceState,
+            20001 => DiscordErrorCode::BotsCannotUseEndpoint,
+            20002 => DiscordErrorCode::OnlyBotsCanUseEndpoint,
+            30001 => DiscordErrorCode::MaxGuildsReached,
+            40001 => DiscordErrorCode::Unauthorized,
+            40002 => DiscordErrorCode::VerificationRequired,
+            50001 => DiscordErrorCode::MissingAccess,
+            50002 => DiscordErrorCode::InvalidAccountType,
+            50003 => DiscordErrorCode::CannotExecuteOnDm,
+            50013 => DiscordErrorCode::MissingPermissions,
+            50014 => DiscordErrorCode::InvalidToken,
+            50035 => DiscordErrorCode::InvalidFormBody,
+            50109 => DiscordErrorCode::InvalidJson,
+            other => DiscordErrorCode::Other(other),
+        }
+    }
+}
+
+impl From<DiscordErrorCode> for i64 {
+    fn from(code: DiscordErrorCode) -> Self {
+        match code {
+            DiscordErrorCode::General => 0,
+            DiscordErrorCode::UnknownAccount => 10001,
+            DiscordErrorCode::UnknownChannel => 10003,
+            DiscordErrorCode::UnknownGuild => 10004,
+            DiscordErrorCode::UnknownMember => 10007,
+            DiscordErrorCode::UnknownRole => 10011,
+            DiscordErrorCode::UnknownUser => 10013,
+            DiscordErrorCode::UnknownVoiceState => 10065,
+            DiscordErrorCode::BotsCannotUseEndpoint => 20001,
+            DiscordErrorCode::OnlyBotsCanUseEndpoint => 20002,
+            DiscordErrorCode::MaxGuildsReached => 30001,
+            DiscordErrorCode::Unauthorized => 40001,
+            DiscordErrorCode::VerificationRequired => 40002,
+            DiscordErrorCode::MissingAccess => 50001,
+            DiscordErrorCode::InvalidAccountType => 50002,
+            DiscordErrorCode::CannotExecuteOnDm => 50003,
+            DiscordErrorCode::MissingPermissions => 50013,
+            DiscordErrorCode::InvalidToken => 50014,
+            DiscordErrorCode::InvalidFormBody => 50035,
+            DiscordErrorCode::InvalidJson => 50109,
+            DiscordErrorCode::Other(code) => code,
+        }
+    }
+}
+
+/// Single validation error from the `errors` tree, e.g. `nick` / `BASE_TYPE_MAX_LENGTH`
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Dotted path to the field, array indices included (`embeds.0.title`)
+    pub path: String,
+    pub code: String,
+    pub message: String,
+}
+
+/// Flattens Discord's nested `errors` object, where every leaf is an `_errors` array, into one
+/// entry per error
+pub fn flatten_errors(errors: &Value) -> Vec<FieldError> {
+    let mut flat = vec![];
+    collect_errors(errors, &mut vec![], &mut flat);
+    flat
+}
+
+fn collect_errors<'a>(node: &'a Value, path: &mut Vec<&'a str>, flat: &mut Vec<FieldError>) {
+    let Value::Object(fields) = node else {
+        return;
+    };
+    for (key, value) in fields {
+        if key == "_errors" {
+            for err in value.as_array().into_iter().flatten() {
+                flat.push(FieldError {
+                    path: path.join("."),
+                    code: err["code"].as_str().unwrap_or_default().to_string(),
+                    message: err["message"].as_str().unwrap_or_default().to_string(),
+                });
+            }
+            continue;
+        }
+        path.push(key.as_str());
+        collect_errors(value, path, flat);
+        path.pop();
+    }
+}
+
+/// Why Discord rejected a request, sent along our own error response
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DiscordErrorDetails {
+    pub status: u16,
+    /// Discord's JSON error code
+    #[schema(value_type = i64)]
+    pub code: DiscordErrorCode,
+    pub message: String,
+    pub errors: Vec<FieldError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn flattens_nested_errors() {
+        let errors = json!({
+            "embeds": {
+                "0": {
+                    "title": {"_errors": [{"code": "BASE_TYPE_REQUIRED", "message": "This field is required"}]}
+                }
+            },
+            "nick": {"_errors": [{"code": "BASE_TYPE_MAX_LENGTH", "message": "Must be 32 or fewer in length."}]}
+        });
+        let mut flat = flatten_errors(&errors);
+        flat.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(flat.len(), 2);
+        assert_eq!(flat[0].path, "embeds.0.title");
+        assert_eq!(flat[0].code, "BASE_TYPE_REQUIRED");
+        assert_eq!(flat[0].message, "This field is required");
+        assert_eq!(flat[1].path, "nick");
+        assert_eq!(flat[1].code, "BASE_TYPE_MAX_LENGTH");
+    }
+
+    #[test]
+    fn top_level_errors_have_empty_path() {
+        let flat = flatten_errors(&json!({"_errors": [{"code": "X", "message": "bad"}]}));
+        assert_eq!(flat.len(), 1);
+        assert_eq!(flat[0].path, "");
+    }
+
+    #[test]
+    fn non_objects_have_no_errors() {
+        assert!(flatten_errors(&json!(null)).is_empty());
+        assert!(flatten_errors(&json!([1, 2])).is_empty());
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        assert_eq!(DiscordErrorCode::from(50035), DiscordErrorCode::InvalidFormBody);
+        assert_eq!(i64::from(DiscordErrorCode::InvalidFormBody), 50035);
+        assert_eq!(DiscordErrorCode::from(12345), DiscordErrorCode::Other(12345));
+        assert_eq!(i64::from(DiscordErrorCode::Other(12345)), 12345);
+    }
+}
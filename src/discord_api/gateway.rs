@@ -0,0 +1,96 @@
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use utoipa::ToSchema;
+use crate::discord_api::DiscordClient;
+use crate::discord_api::token::TokenKind;
+
+/// Used when discovery fails and nothing is cached yet
+const DEFAULT_GATEWAY_URL: &str = "wss://gateway.discord.gg";
+/// Used when `GATEWAY_URL_TTL_SECS` isn't set
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+/// Gateway version and encoding every connection asks for
+pub const GATEWAY_QUERY: &str = "?v=10&encoding=json";
+
+lazy_static! {
+    static ref CACHED_URL: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+}
+
+/// Response of `GET /gateway`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GatewayInfo {
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionStartLimit {
+    pub total: u32,
+    pub remaining: u32,
+    /// Milliseconds until `remaining` resets
+    pub reset_after: u64,
+    pub max_concurrency: u32,
+}
+
+/// Response of `GET /gateway/bot`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GatewayBotInfo {
+    pub url: String,
+    pub shards: u32,
+    pub session_start_limit: SessionStartLimit,
+}
+
+fn ttl() -> Duration {
+    env::var("GATEWAY_URL_TTL_SECS").ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TTL)
+}
+
+/// Url to connect the gateway to, without the query. `GATEWAY_URL` overrides discovery (e.g. to
+/// point at a local stand-in), otherwise the url is fetched from Discord and cached for
+/// `GATEWAY_URL_TTL_SECS`. A stale cached url is used if fetching fails.
+pub async fn gateway_url(api: &DiscordClient, kind: TokenKind) -> String {
+    if let Ok(url) = env::var("GATEWAY_URL") {
+        return url;
+    }
+    let cached = CACHED_URL.lock().unwrap().clone();
+    if let Some((url, fetched_at)) = &cached {
+        if fetched_at.elapsed() < ttl() {
+            return url.clone();
+        }
+    }
+
+    let fetched = match kind {
+        TokenKind::Bot => api.get_gateway_bot().await.map(|info| info.url),
+        _ => api.get_gateway().await.map(|info| info.url),
+    };
+    match (fetched, cached) {
+        (Ok(url), _) => {
+            *CACHED_URL.lock().unwrap() = Some((url.clone(), Instant::now()));
+            url
+        },
+        (Err(e), Some((url, _))) => {
+            warn!("couldn't fetch gateway url, using cached {url}: {e}");
+            url
+        },
+        (Err(e), None) => {
+            error!("couldn't fetch gateway url, using {DEFAULT_GATEWAY_URL}: {e}");
+            String::from(DEFAULT_GATEWAY_URL)
+        }
+    }
+}
+
+/// Client properties sent on identify, unset ones fall back to the token kind's defaults
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct IdentifyOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browser: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::discord_api::snowflake::{ChannelId, GuildId, MessageId, Snowflake, UserId};
+
+/// User object, fields only sent with the `email` scope or to the user itself are optional
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiscordUser {
+    pub id: UserId,
+    pub username: String,
+    pub discriminator: String,
+    pub global_name: Option<String>,
+    pub avatar: Option<String>,
+    pub bot: Option<bool>,
+    pub system: Option<bool>,
+    pub mfa_enabled: Option<bool>,
+    pub banner: Option<String>,
+    pub accent_color: Option<i64>,
+    pub locale: Option<String>,
+    pub verified: Option<bool>,
+    pub email: Option<String>,
+    pub flags: Option<i64>,
+    pub premium_type: Option<i32>,
+    pub public_flags: Option<i64>,
+}
+
+/// Guild as returned by `/users/@me/guilds`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartialGuild {
+    pub id: GuildId,
+    pub name: String,
+    pub icon: Option<String>,
+    pub owner: Option<bool>,
+    /// Current user's permissions in the guild, bitset as string
+    pub permissions: Option<String>,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Role {
+    pub id: Snowflake,
+    pub name: String,
+    pub color: i64,
+    pub hoist: bool,
+    pub position: i32,
+    /// Bitset as string
+    pub permissions: String,
+    pub managed: bool,
+    pub mentionable: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Guild {
+    pub id: GuildId,
+    pub name: String,
+    pub icon: Option<String>,
+    pub owner_id: UserId,
+    pub afk_channel_id: Option<ChannelId>,
+    pub afk_timeout: i32,
+    pub verification_level: i32,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub system_channel_id: Option<ChannelId>,
+    pub approximate_member_count: Option<i32>,
+    pub approximate_presence_count: Option<i32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PermissionOverwrite {
+    /// Role or user id depending on `kind`
+    pub id: Snowflake,
+    /// 0 for role, 1 for member
+    #[serde(rename = "type")]
+    pub kind: i32,
+    pub allow: String,
+    pub deny: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Channel {
+    pub id: ChannelId,
+    /// 0 text, 2 voice, 4 category, 13 stage...
+    #[serde(rename = "type")]
+    pub kind: i32,
+    pub guild_id: Option<GuildId>,
+    pub name: Option<String>,
+    pub position: Option<i32>,
+    pub parent_id: Option<ChannelId>,
+    pub topic: Option<String>,
+    pub nsfw: Option<bool>,
+    pub bitrate: Option<i32>,
+    pub user_limit: Option<i32>,
+    pub rtc_region: Option<String>,
+    #[serde(default)]
+    pub permission_overwrites: Vec<PermissionOverwrite>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GuildMember {
+    pub user: Option<DiscordUser>,
+    pub nick: Option<String>,
+    pub avatar: Option<String>,
+    pub roles: Vec<String>,
+    pub joined_at: String,
+    pub premium_since: Option<String>,
+    pub deaf: Option<bool>,
+    pub mute: Option<bool>,
+    pub flags: i32,
+    pub pending: Option<bool>,
+    pub communication_disabled_until: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VoiceRegion {
+    pub id: String,
+    pub name: String,
+    pub optimal: bool,
+    pub deprecated: bool,
+    pub custom: bool,
+}
+
+/// User's voice connection in a guild, `channel_id` is `None` once they left
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct VoiceState {
+    /// Missing from the voice states nested in guild objects
+    pub guild_id: Option<GuildId>,
+    pub channel_id: Option<ChannelId>,
+    pub user_id: UserId,
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default)]
+    pub deaf: bool,
+    #[serde(default)]
+    pub mute: bool,
+    #[serde(default)]
+    pub self_deaf: bool,
+    #[serde(default)]
+    pub self_mute: bool,
+    #[serde(default)]
+    pub self_stream: bool,
+    #[serde(default)]
+    pub self_video: bool,
+    #[serde(default)]
+    pub suppress: bool,
+}
+
+/// Body of `PATCH /users/@me`, unset fields are left unchanged
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ModifyCurrentUser {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Image data URI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmbedFooter {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmbedMedia {
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmbedAuthor {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+/// Rich embed, every field is optional but Discord rejects an entirely empty one
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Embed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// ISO8601 timestamp shown in the footer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<EmbedFooter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<EmbedMedia>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<EmbedMedia>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<EmbedAuthor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EmbedField>,
+}
+
+/// Which mentions in the content actually ping, see Discord's allowed mentions object
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AllowedMentions {
+    /// Any of `roles`, `users` and `everyone`
+    #[serde(default)]
+    pub parse: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<Snowflake>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<UserId>,
+    #[serde(default)]
+    pub replied_user: bool,
+}
+
+/// Message being replied to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MessageReference {
+    pub message_id: MessageId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<ChannelId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<GuildId>,
+    /// Send the message anyway if the referenced one is gone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fail_if_not_exists: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: Snowflake,
+    pub filename: String,
+    pub size: u64,
+    pub url: String,
+    pub content_type: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Message {
+    pub id: MessageId,
+    pub channel_id: ChannelId,
+    pub author: DiscordUser,
+    pub content: String,
+    pub timestamp: String,
+    pub edited_timestamp: Option<String>,
+    #[serde(default)]
+    pub embeds: Vec<Embed>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    pub message_reference: Option<MessageReference>,
+}
+
+/// Body of `POST /channels/{channel_id}/messages`, one of content, embeds or files is required
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CreateMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embeds: Vec<Embed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_mentions: Option<AllowedMentions>,
+    /// Set to reply to a message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_reference: Option<MessageReference>,
+}
+
+/// Body of `PATCH /channels/{channel_id}/messages/{message_id}`, unset fields are left unchanged
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EditMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embeds: Option<Vec<Embed>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_mentions: Option<AllowedMentions>,
+}
+
+/// File uploaded along a message, `data` is base64 in json
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileUpload {
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+mod base64_bytes {
+    use base64::Engine;
+    use base64::prelude::BASE64_STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64_STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Message with the files to upload with it
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OutgoingMessage {
+    #[serde(flatten)]
+    pub message: CreateMessage,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileUpload>,
+}
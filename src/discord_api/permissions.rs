@@ -0,0 +1,47 @@
+use crate::discord_api::models::{Channel, Guild, GuildMember};
+use crate::discord_api::snowflake::UserId;
+
+pub const ADMINISTRATOR: u64 = 1 << 3;
+pub const VIEW_CHANNEL: u64 = 1 << 10;
+pub const CONNECT: u64 = 1 << 20;
+pub const MOVE_MEMBERS: u64 = 1 << 24;
+
+/// Permissions are sent as decimal strings
+fn parse(bits: &str) -> u64 {
+    bits.parse().unwrap_or(0)
+}
+
+/// Member's permissions in the guild from its roles and @everyone
+pub fn base_permissions(guild: &Guild, member: &GuildMember, user_id: UserId) -> u64 {
+    if guild.owner_id == user_id {
+        return u64::MAX;
+    }
+    let permissions = guild.roles.iter()
+        .filter(|role| role.id == guild.id.0 || member.roles.contains(&role.id.to_string()))
+        .fold(0, |acc, role| acc | parse(&role.permissions));
+    if permissions & ADMINISTRATOR != 0 {
+        return u64::MAX;
+    }
+    permissions
+}
+
+/// Member's permissions in the channel, applying the channel's overwrites in Discord's order:
+/// @everyone, then the member's roles together, then the member itself
+pub fn channel_permissions(guild: &Guild, member: &GuildMember, user_id: UserId, channel: &Channel) -> u64 {
+    let mut permissions = base_permissions(guild, member, user_id);
+    if permissions == u64::MAX {
+        return permissions;
+    }
+    let overwrites = &channel.permission_overwrites;
+    if let Some(everyone) = overwrites.iter().find(|o| o.id == guild.id.0) {
+        permissions = (permissions & !parse(&everyone.deny)) | parse(&everyone.allow);
+    }
+    let (allow, deny) = overwrites.iter()
+        .filter(|o| o.kind == 0 && member.roles.contains(&o.id.to_string()))
+        .fold((0, 0), |(allow, deny), o| (allow | parse(&o.allow), deny | parse(&o.deny)));
+    permissions = (permissions & !deny) | allow;
+    if let Some(own) = overwrites.iter().find(|o| o.kind == 1 && o.id == user_id.0) {
+        permissions = (permissions & !parse(&own.deny)) | parse(&own.allow);
+    }
+    permissions
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use lazy_static::lazy_static;
+use log::warn;
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::time::{self, Instant};
+use crate::discord_api::loggable_route;
+
+lazy_static! {
+    /// Limiters are shared by every client using the same token, keyed by the token's hash
+    static ref LIMITERS: Mutex<HashMap<String, Arc<RateLimiter>>> = Mutex::new(HashMap::new());
+}
+
+/// Body Discord sends along a 429
+#[derive(Deserialize, Debug)]
+pub struct RateLimited {
+    pub message: String,
+    /// Seconds to wait before retrying
+    pub retry_after: f64,
+    #[serde(default)]
+    pub global: bool,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    remaining: u32,
+    reset_at: Instant,
+}
+
+/// Tracks Discord's REST rate limits for a single token
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Route key -> bucket hash Discord told us the route belongs to
+    routes: Mutex<HashMap<String, String>>,
+    /// Bucket key (bucket hash + major parameters, or route key until the hash is known) -> state
+    buckets: Mutex<HashMap<String, BucketState>>,
+    global_until: Mutex<Option<Instant>>,
+}
+
+/// Path segments whose following id is a major parameter, routes with different major
+/// parameters never share a bucket
+const MAJOR_PARAMS: [&str; 3] = ["channels", "guilds", "webhooks"];
+
+/// Strips the query and replaces ids that aren't major parameters so every request to the same
+/// route gets the same key, e.g. `GET /guilds/1/members/2` -> `GET /guilds/1/members/:id`
+fn route_key(method: &Method, route: &str) -> String {
+    let path = route.split('?').next().unwrap_or(route);
+    let mut key = method.to_string();
+    let mut prev = "";
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        key.push('/');
+        let is_id = segment.chars().all(|c| c.is_ascii_digit());
+        if is_id && !MAJOR_PARAMS.contains(&prev) {
+            key.push_str(":id");
+        } else {
+            key.push_str(segment);
+        }
+        prev = segment;
+    }
+    key
+}
+
+/// Major parameters of the route joined, used to split a shared bucket hash per resource
+fn major_params(route: &str) -> String {
+    let path = route.split('?').next().unwrap_or(route);
+    let segments = path.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    segments.windows(2)
+        .filter(|pair| MAJOR_PARAMS.contains(&pair[0]))
+        .map(|pair| pair[1])
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn limiter_key(token: &str) -> String {
+    hex::encode(Sha256::digest(token))
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+impl RateLimiter {
+    pub fn for_token(token: &str) -> Arc<RateLimiter> {
+        LIMITERS.lock().unwrap().entry(limiter_key(token)).or_default().clone()
+    }
+
+    /// Drops the token's limiter once its account is deleted or the token rotated out, clients
+    /// still using the token keep their limiter
+    pub fn forget_token(token: &str) {
+        LIMITERS.lock().unwrap().remove(&limiter_key(token));
+    }
+
+    fn bucket_key(&self, method: &Method, route: &str) -> String {
+        let key = route_key(method, route);
+        match self.routes.lock().unwrap().get(&key) {
+            None => key,
+            Some(bucket) => format!("{}:{}", bucket, major_params(route))
+        }
+    }
+
+    /// Waits until a request to `route` can be sent without hitting a known limit
+    pub async fn acquire(&self, method: &Method, route: &str) {
+        loop {
+            let now = Instant::now();
+            let global = *self.global_until.lock().unwrap();
+            if let Some(until) = global.filter(|until| *until > now) {
+                time::sleep_until(until).await;
+                continue;
+            }
+
+            let key = self.bucket_key(method, route);
+            let wait_until = {
+                let mut buckets = self.buckets.lock().unwrap();
+                match buckets.get_mut(&key) {
+                    Some(state) if state.reset_at <= now => {
+                        buckets.remove(&key);
+                        None
+                    },
+                    Some(state) if state.remaining == 0 => Some(state.reset_at),
+                    Some(state) => {
+                        state.remaining -= 1;
+                        None
+                    },
+                    None => None
+                }
+            };
+            match wait_until {
+                None => return,
+                Some(until) => time::sleep_until(until).await
+            }
+        }
+    }
+
+    /// Updates the route's bucket from the `X-RateLimit-*` headers of a response
+    pub fn update(&self, method: &Method, route: &str, headers: &HeaderMap) {
+        if let Some(bucket) = header(headers, "x-ratelimit-bucket") {
+            self.routes.lock().unwrap().insert(route_key(method, route), bucket.to_string());
+        }
+        let remaining = header(headers, "x-ratelimit-remaining").and_then(|v| v.parse::<u32>().ok());
+        let reset_after = header(headers, "x-ratelimit-reset-after").and_then(|v| v.parse::<f64>().ok());
+        if let (Some(remaining), Some(reset_after)) = (remaining, reset_after) {
+            let reset_at = Instant::now() + Duration::from_secs_f64(reset_after.max(0.0));
+            self.buckets.lock().unwrap().insert(self.bucket_key(method, route), BucketState { remaining, reset_at });
+        }
+    }
+
+    /// Records a 429, every request waits out a global limit, otherwise only the route's bucket
+    pub fn rate_limited(&self, method: &Method, route: &str, limited: &RateLimited) {
+        let retry_after = Duration::from_secs_f64(limited.retry_after.max(0.0));
+        warn!("rate limited on {method} {}{}, retrying after {:?}", loggable_route(route), if limited.global { " (global)" } else { "" }, retry_after);
+        let until = Instant::now() + retry_after;
+        if limited.global {
+            *self.global_until.lock().unwrap() = Some(until);
+        } else {
+            self.buckets.lock().unwrap().insert(self.bucket_key(method, route), BucketState { remaining: 0, reset_at: until });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+    use super::*;
+
+    #[test]
+    fn route_key_replaces_minor_ids() {
+        assert_eq!(route_key(&Method::GET, "/guilds/1/members/2"), "GET/guilds/1/members/:id");
+        assert_eq!(route_key(&Method::DELETE, "/channels/1/messages/2"), "DELETE/channels/1/messages/:id");
+        assert_eq!(route_key(&Method::PATCH, "/users/@me"), "PATCH/users/@me");
+    }
+
+    #[test]
+    fn route_key_strips_query() {
+        assert_eq!(route_key(&Method::GET, "/channels/1/messages?limit=50"), "GET/channels/1/messages");
+    }
+
+    #[test]
+    fn major_params_joined() {
+        assert_eq!(major_params("/guilds/1/channels/2/messages/3"), "1:2");
+        assert_eq!(major_params("/webhooks/5/token?wait=true"), "5");
+        assert_eq!(major_params("/users/@me"), "");
+    }
+
+    #[test]
+    fn bucket_key_uses_hash_once_known() {
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.bucket_key(&Method::GET, "/channels/1/messages/2"), "GET/channels/1/messages/:id");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-bucket", HeaderValue::from_static("abc"));
+        limiter.update(&Method::GET, "/channels/1/messages/2", &headers);
+        assert_eq!(limiter.bucket_key(&Method::GET, "/channels/1/messages/3"), "abc:1");
+        // major parameters are part of the route key, other channels learn their bucket separately
+        assert_eq!(limiter.bucket_key(&Method::GET, "/channels/4/messages/2"), "GET/channels/4/messages/:id");
+    }
+}
@@ -0,0 +1,81 @@
+use std::env;
+use std::time::Duration;
+use rand::Rng;
+use reqwest::{Method, Response};
+
+/// Used when `DISCORD_REQUEST_TIMEOUT_SECS` isn't set
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Timeout of a single request to Discord, retries get their own
+pub fn request_timeout() -> Duration {
+    env::var("DISCORD_REQUEST_TIMEOUT_SECS").ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// When failed requests are sent again. Only idempotent methods are retried since a request
+/// that timed out may still have gone through
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn is_idempotent(method: &Method) -> bool {
+        [Method::GET, Method::HEAD, Method::OPTIONS, Method::PUT, Method::DELETE].contains(method)
+    }
+
+    /// Whether `attempt` (starting at 1) failed transiently and another one is allowed
+    pub fn should_retry(&self, method: &Method, attempt: u32, result: &reqwest::Result<Response>) -> bool {
+        if attempt >= self.max_attempts || !Self::is_idempotent(method) {
+            return false;
+        }
+        match result {
+            Ok(resp) => resp.status().is_server_error(),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        }
+    }
+
+    /// Exponential delay after `attempt` with jitter so bots failing together don't retry together
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        exp.min(self.max_delay).mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_between(delay: Duration, min: Duration, max: Duration) {
+        assert!(delay >= min && delay <= max, "{delay:?} not in {min:?}..={max:?}");
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter() {
+        let policy = RetryPolicy::default();
+        assert_between(policy.backoff(1), Duration::from_millis(125), Duration::from_millis(250));
+        assert_between(policy.backoff(3), Duration::from_millis(500), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_capped() {
+        let policy = RetryPolicy::default();
+        assert_between(policy.backoff(10), Duration::from_millis(2500), Duration::from_secs(5));
+        assert_between(policy.backoff(u32::MAX), Duration::from_millis(2500), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::io::Write;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor};
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
+
+/// First second of 2015, the epoch Discord's snowflakes count from, in milliseconds
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// Discord id. Serialized as a string since JavaScript numbers can't hold every id, both forms
+/// are accepted when deserializing. Stored as text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub struct Snowflake(pub u64);
+
+impl Snowflake {
+    /// When the id was created
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        let millis = (self.0 >> 22) + DISCORD_EPOCH;
+        Utc.timestamp_millis_opt(millis as i64).single().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Snowflake)
+    }
+}
+
+impl Serialize for Snowflake {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct SnowflakeVisitor;
+
+impl Visitor<'_> for SnowflakeVisitor {
+    type Value = Snowflake;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a snowflake as a string or integer")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Snowflake(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        u64::try_from(v).map(Snowflake).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Snowflake {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SnowflakeVisitor)
+    }
+}
+
+impl ToSql<Text, Pg> for Snowflake {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write!(out, "{}", self.0)?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for Snowflake {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        Ok(s.parse()?)
+    }
+}
+
+/// Snowflakes are documented as the strings they're serialized as
+fn snowflake_schema() -> RefOr<Schema> {
+    RefOr::T(Schema::Object(ObjectBuilder::new()
+        .schema_type(SchemaType::String)
+        .description(Some("Discord id"))
+        .example(Some("175928847299117063".into()))
+        .build()))
+}
+
+impl<'s> ToSchema<'s> for Snowflake {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        ("Snowflake", snowflake_schema())
+    }
+}
+
+/// Declares a snowflake for one kind of resource so ids of different kinds can't be mixed up
+macro_rules! typed_snowflake {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, AsExpression, FromSqlRow)]
+        #[serde(transparent)]
+        #[diesel(sql_type = Text)]
+        pub struct $name(pub Snowflake);
+
+        impl $name {
+            /// When the resource was created
+            pub fn timestamp(&self) -> DateTime<Utc> {
+                self.0.timestamp()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map($name)
+            }
+        }
+
+        impl ToSql<Text, Pg> for $name {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                <Snowflake as ToSql<Text, Pg>>::to_sql(&self.0, out)
+            }
+        }
+
+        impl FromSql<Text, Pg> for $name {
+            fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                <Snowflake as FromSql<Text, Pg>>::from_sql(bytes).map($name)
+            }
+        }
+
+        impl<'s> ToSchema<'s> for $name {
+            fn schema() -> (&'s str, RefOr<Schema>) {
+                (stringify!($name), snowflake_schema())
+            }
+        }
+    };
+}
+
+typed_snowflake!(
+    /// Id of a guild
+    GuildId
+);
+typed_snowflake!(
+    /// Id of a channel, voice and text alike
+    ChannelId
+);
+typed_snowflake!(
+    /// Id of a user, bot accounts included
+    UserId
+);
+typed_snowflake!(
+    /// Id of a message, only unique within its channel
+    MessageId
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_strings_and_numbers() {
+        let from_str: Snowflake = serde_json::from_str("\"175928847299117063\"").unwrap();
+        let from_num: Snowflake = serde_json::from_str("175928847299117063").unwrap();
+        assert_eq!(from_str, Snowflake(175928847299117063));
+        assert_eq!(from_num, from_str);
+    }
+
+    #[test]
+    fn serializes_as_string() {
+        assert_eq!(serde_json::to_string(&Snowflake(175928847299117063)).unwrap(), "\"175928847299117063\"");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(serde_json::from_str::<Snowflake>("-1").is_err());
+        assert!(serde_json::from_str::<Snowflake>("\"abc\"").is_err());
+        assert!(serde_json::from_str::<Snowflake>("\"-1\"").is_err());
+    }
+
+    #[test]
+    fn typed_ids_are_transparent() {
+        let guild: GuildId = serde_json::from_str("\"42\"").unwrap();
+        assert_eq!(guild, GuildId(Snowflake(42)));
+        assert_eq!(serde_json::to_string(&guild).unwrap(), "\"42\"");
+        assert_eq!("42".parse::<GuildId>().unwrap(), guild);
+    }
+
+    #[test]
+    fn timestamp_from_id() {
+        assert_eq!(Snowflake(175928847299117063).timestamp().to_rfc3339(), "2016-04-30T11:18:25.796+00:00");
+    }
+}
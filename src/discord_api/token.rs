@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::USER_AGENT;
+
+/// User agent Discord requires for bot applications
+const BOT_USER_AGENT: &str = concat!("DiscordBot (https://github.com/cchosch/feeble-bot, ", env!("CARGO_PKG_VERSION"), ")");
+
+/// GUILDS | GUILD_VOICE_STATES, enough to track guilds and move around in voice. None of them
+/// are privileged so bots don't need extra approval
+const BOT_INTENTS: u64 = (1 << 0) | (1 << 7);
+
+/// Kind of credential a controlled account authenticates with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    /// Official bot application token
+    Bot,
+    /// OAuth2 access token, REST only
+    Bearer,
+    /// Raw token of a user account
+    #[default]
+    User,
+}
+
+impl TokenKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenKind::Bot => "bot",
+            TokenKind::Bearer => "bearer",
+            TokenKind::User => "user",
+        }
+    }
+
+    /// Parses the value stored in the database, unknown values are treated as user tokens
+    pub fn from_db(kind: &str) -> Self {
+        match kind {
+            "bot" => TokenKind::Bot,
+            "bearer" => TokenKind::Bearer,
+            _ => TokenKind::User,
+        }
+    }
+
+    /// Value of the `Authorization` header for `token`
+    pub fn authorization(&self, token: &str) -> String {
+        match self {
+            TokenKind::Bot => format!("Bot {token}"),
+            TokenKind::Bearer => format!("Bearer {token}"),
+            TokenKind::User => token.to_string(),
+        }
+    }
+
+    pub fn user_agent(&self) -> &'static str {
+        match self {
+            TokenKind::Bot | TokenKind::Bearer => BOT_USER_AGENT,
+            TokenKind::User => USER_AGENT,
+        }
+    }
+
+    /// OAuth2 tokens can't identify on the gateway
+    pub fn has_gateway(&self) -> bool {
+        !matches!(self, TokenKind::Bearer)
+    }
+
+    /// Gateway intents sent on identify, user accounts don't send any
+    pub fn intents(&self) -> Option<u64> {
+        match self {
+            TokenKind::Bot => Some(BOT_INTENTS),
+            _ => None,
+        }
+    }
+}
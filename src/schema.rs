@@ -1,5 +1,21 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    account_group (id) {
+        id -> Varchar,
+        name -> Text,
+        created_by -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    account_group_member (group_id, account_id) {
+        group_id -> Varchar,
+        account_id -> Varchar,
+    }
+}
+
 diesel::table! {
     account_mapping (id) {
         id -> Varchar,
@@ -10,6 +26,36 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    api_token (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        name -> Text,
+        token_hash -> Varchar,
+        scopes -> Array<Text>,
+        created_at -> Timestamptz,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Varchar,
+        actor_id -> Nullable<Varchar>,
+        action -> Text,
+        target_type -> Nullable<Text>,
+        target_id -> Nullable<Varchar>,
+        ip -> Nullable<Text>,
+        forwarded_for -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        success -> Bool,
+        error -> Nullable<Text>,
+        details -> Nullable<Jsonb>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     controlled_account (id) {
         id -> Varchar,
@@ -17,6 +63,14 @@ diesel::table! {
         username -> Varchar,
         token -> Varchar,
         created_by -> Varchar,
+        enabled -> Bool,
+        dry_run -> Bool,
+        token_kind -> Varchar,
+        token_status -> Varchar,
+        last_checked -> Nullable<Timestamptz>,
+        last_error -> Nullable<Varchar>,
+        label -> Nullable<Text>,
+        identify_options -> Nullable<Jsonb>,
     }
 }
 
@@ -30,6 +84,43 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    invite (code) {
+        code -> Varchar,
+        created_by -> Varchar,
+        email -> Nullable<Text>,
+        flags -> Int8,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_by -> Nullable<Varchar>,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    job_run (id) {
+        id -> Varchar,
+        job_id -> Varchar,
+        ran_at -> Timestamptz,
+        success -> Bool,
+        error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    scheduled_job (id) {
+        id -> Varchar,
+        account_id -> Varchar,
+        command -> Jsonb,
+        run_at -> Nullable<Timestamptz>,
+        cron -> Nullable<Text>,
+        next_run -> Nullable<Timestamptz>,
+        enabled -> Bool,
+        created_by -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     sessions (sess_id) {
         sess_id -> Text,
@@ -54,10 +145,32 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook (id) {
+        id -> Varchar,
+        name -> Text,
+        discord_id -> Varchar,
+        encrypted_url -> Varchar,
+        created_by -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::joinable!(api_token -> users (user_id));
+diesel::joinable!(audit_log -> users (actor_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    account_group,
+    account_group_member,
     account_mapping,
+    api_token,
+    audit_log,
     controlled_account,
     discord_account,
+    invite,
+    job_run,
+    scheduled_job,
     sessions,
     users,
+    webhook,
 );
@@ -0,0 +1,116 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel::result::DatabaseErrorKind;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use log::error;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::api::DbConn;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::conv_search_err;
+use crate::db::gen_id;
+use crate::schema::account_group::dsl::account_group;
+use crate::schema::account_group::{created_by as db_created_by, id, name as db_name};
+use crate::schema::account_group_member::dsl::account_group_member;
+use crate::schema::account_group_member::{account_id, group_id};
+
+/// Named set of controlled accounts that commands can be sent to together
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::account_group)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AccountGroup {
+    pub id: String,
+    pub name: String,
+    pub created_by: String,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable)]
+#[diesel(table_name = crate::schema::account_group_member)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AccountGroupMember {
+    pub group_id: String,
+    pub account_id: String,
+}
+
+fn map_write_err(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::BadRequest(String::from("A group with that name already exists"))
+        },
+        _ => map_db_err(e)
+    }
+}
+
+impl AccountGroup {
+    pub fn new(name: String, created_by: String) -> Self {
+        Self {
+            id: gen_id(),
+            name,
+            created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(account_group).values(self).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(group: String, conn: &mut DbConn) -> ApiResult<AccountGroup> {
+        account_group.filter(id.eq(group)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    pub async fn list_by_creator(uid: String, conn: &mut DbConn) -> ApiResult<Vec<AccountGroup>> {
+        account_group.filter(db_created_by.eq(uid)).order(db_name.asc()).load(conn).await.map_err(map_db_err)
+    }
+
+    pub async fn rename(&mut self, new_name: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(account_group.filter(id.eq(self.id.clone())))
+            .set(db_name.eq(new_name.clone()))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.name = new_name;
+        Ok(())
+    }
+
+    /// Deletes group along with its memberships, all or none of them
+    pub async fn delete_by_id(group: String, conn: &mut DbConn) -> ApiResult<()> {
+        conn.transaction::<_, ApiError, _>(|conn| async move {
+            diesel::delete(account_group_member.filter(group_id.eq(group.clone()))).execute(conn).await.map_err(map_write_err)?;
+            diesel::delete(account_group.filter(id.eq(group))).execute(conn).await.map_err(map_write_err)?;
+            Ok(())
+        }.scope_boxed()).await
+    }
+
+    /// Controlled account ids in the group
+    pub async fn member_ids(&self, conn: &mut DbConn) -> ApiResult<Vec<String>> {
+        account_group_member
+            .filter(group_id.eq(self.id.clone()))
+            .select(account_id)
+            .load(conn).await.map_err(map_db_err)
+    }
+
+    pub async fn add_member(&self, account: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(account_group_member)
+            .values(AccountGroupMember { group_id: self.id.clone(), account_id: account })
+            .on_conflict_do_nothing()
+            .execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    /// Removes account from every group it's a member of
+    pub async fn remove_account(account: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::delete(account_group_member.filter(account_id.eq(account))).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn remove_member(&self, account: String, conn: &mut DbConn) -> ApiResult<()> {
+        let removed = diesel::delete(account_group_member.filter(group_id.eq(self.id.clone())).filter(account_id.eq(account)))
+            .execute(conn).await.map_err(map_write_err)?;
+        if removed == 0 {
+            return Err(ApiError::NotFound);
+        }
+        Ok(())
+    }
+}
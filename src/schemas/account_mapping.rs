@@ -1,47 +1,86 @@
 use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel::result::DatabaseErrorKind;
 use diesel_async::RunQueryDsl;
 use log::error;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use crate::api::DbConn;
-use crate::api::err::{ApiError, ApiResult};
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::conv_search_err;
 use crate::db::gen_id;
 use crate::schema::account_mapping::dsl::account_mapping;
-use crate::schema::account_mapping::id;
+use crate::schema::account_mapping::{controlled_discord_id as db_controlled_discord_id, controlled_internal_id as db_controlled_internal_id, controlled_username as db_controlled_username, id, mapped_discord_id as db_mapped_discord_id};
+use crate::discord_api::snowflake::UserId;
 use crate::schemas::controlled_account::ControlledAccount;
 
-#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable)]
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
 #[diesel(table_name = crate::schema::account_mapping)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct AccountMapping {
-    id: String,
-    mapped_discord_id: String,
-    controlled_username: String,
-    controlled_discord_id: String,
-    controlled_internal_id: String,
+    pub id: String,
+    pub mapped_discord_id: UserId,
+    /// Copy of the controlled account's username, kept in sync when it changes
+    pub controlled_username: String,
+    pub controlled_discord_id: UserId,
+    pub controlled_internal_id: String,
 }
 
+fn map_write_err(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::BadRequest(String::from("Account is already mapped to that user"))
+        },
+        _ => map_db_err(e)
+    }
+}
 
 impl AccountMapping {
-    pub fn new(controlled_account: &ControlledAccount, mapped_discord_id: String) -> Self {
+    pub fn new(controlled_account: &ControlledAccount, mapped_discord_id: UserId) -> Self {
         Self {
             id: gen_id(),
             mapped_discord_id,
             controlled_username: controlled_account.username.clone(),
             controlled_internal_id: controlled_account.id.clone(),
-            controlled_discord_id: controlled_account.discord_id.clone()
+            controlled_discord_id: controlled_account.discord_id
         }
     }
 
     pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
-        match diesel::insert_into(account_mapping).values(self).execute(conn).await {
-            Err(e) => {
-                error!("{e}");
-                Err(ApiError::InternalError)
-            },
-            Ok(_v) => {
-                Ok(())
-            }
-        }
+        diesel::insert_into(account_mapping).values(self).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(mapping: String, conn: &mut DbConn) -> ApiResult<AccountMapping> {
+        account_mapping.filter(id.eq(mapping)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    pub async fn list_by_account(internal_id: String, conn: &mut DbConn) -> ApiResult<Vec<AccountMapping>> {
+        account_mapping.filter(db_controlled_internal_id.eq(internal_id)).order(db_mapped_discord_id.asc()).load(conn).await.map_err(map_db_err)
     }
-}
 
+    pub async fn set_mapped_discord_id(&mut self, mapped_discord_id: UserId, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(account_mapping.filter(id.eq(self.id.clone())))
+            .set(db_mapped_discord_id.eq(mapped_discord_id))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.mapped_discord_id = mapped_discord_id;
+        Ok(())
+    }
+
+    /// Copies the account's current Discord id and username to its mappings
+    pub async fn sync_account(account: &ControlledAccount, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(account_mapping.filter(db_controlled_internal_id.eq(account.id.clone())))
+            .set((db_controlled_discord_id.eq(account.discord_id), db_controlled_username.eq(account.username.clone())))
+            .execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn delete_by_id(mapping: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::delete(account_mapping.filter(id.eq(mapping))).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn delete_for_account(internal_id: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::delete(account_mapping.filter(db_controlled_internal_id.eq(internal_id))).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel_async::RunQueryDsl;
+use log::error;
+use rand::random;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use crate::api::DbConn;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::conv_search_err;
+use crate::db::gen_id;
+use crate::schema::api_token::dsl::api_token;
+use crate::schema::api_token::{created_at as db_created_at, id, last_used_at as db_last_used_at, token_hash as db_token_hash, user_id as db_user_id};
+
+/// Prefix of every token so leaked ones are easy to recognize
+const TOKEN_PREFIX: &str = "fbt_";
+
+/// What a token may be used for
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// `GET` requests
+    Read,
+    /// Every other method
+    Write,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+}
+
+/// Personal token a user's scripts authenticate with through `Authorization: Bearer`, only its
+/// hash is stored
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::api_token)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    #[serde(skip_serializing)]
+    token_hash: String,
+    /// Names of the token's [`TokenScope`]s
+    pub scopes: Vec<String>,
+    created_at: NaiveDateTime,
+    /// Never expires when `None`
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token))
+}
+
+impl ApiToken {
+    /// New token for `user_id` along with its plaintext, which is only known at this point
+    pub fn new(user_id: String, name: String, scopes: &[TokenScope], valid_for: Option<Duration>) -> ApiResult<(Self, String)> {
+        let token = format!("{TOKEN_PREFIX}{}", hex::encode(random::<[u8; 32]>()));
+        let now = Utc::now().naive_utc();
+        let mut scopes: Vec<String> = scopes.iter().map(|scope| scope.as_str().to_string()).collect();
+        scopes.sort();
+        scopes.dedup();
+        let expires_at = match valid_for {
+            None => None,
+            Some(valid_for) => Some(now.checked_add_signed(valid_for).ok_or(ApiError::BadRequest(String::from("Token expiry is too far away")))?),
+        };
+        Ok((Self {
+            id: gen_id(),
+            user_id,
+            name,
+            token_hash: hash_token(&token),
+            scopes,
+            created_at: now,
+            expires_at,
+            last_used_at: None,
+        }, token))
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(api_token).values(self).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Token matching the plaintext `token`, not found for unknown tokens
+    pub async fn get_by_token(token: &str, conn: &mut DbConn) -> ApiResult<ApiToken> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Err(ApiError::NotFound);
+        }
+        api_token.filter(db_token_hash.eq(hash_token(token))).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    pub async fn get_by_id(token_id: String, conn: &mut DbConn) -> ApiResult<ApiToken> {
+        api_token.filter(id.eq(token_id)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    pub async fn list_by_user(uid: String, conn: &mut DbConn) -> ApiResult<Vec<ApiToken>> {
+        api_token.filter(db_user_id.eq(uid)).order(db_created_at.desc()).load(conn).await.map_err(map_db_err)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    }
+
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.scopes.iter().any(|s| s == scope.as_str())
+    }
+
+    pub async fn touch(&mut self, conn: &mut DbConn) -> ApiResult<()> {
+        let now = Utc::now().naive_utc();
+        diesel::update(api_token.filter(id.eq(self.id.clone())))
+            .set(db_last_used_at.eq(Some(now)))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.last_used_at = Some(now);
+        Ok(())
+    }
+
+    pub async fn delete_by_id(token_id: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::delete(api_token.filter(id.eq(token_id))).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::pg::Pg;
+use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::{IntoParams, ToSchema};
+use crate::api::DbConn;
+use crate::api::audit::RequestMeta;
+use crate::api::err::{map_db_err, ApiResult};
+use crate::bots::manager::BotCommand;
+use crate::db::gen_id;
+use crate::schema::audit_log;
+use crate::schema::audit_log::dsl::audit_log as audit_log_table;
+use crate::schema::audit_log::{action as db_action, actor_id as db_actor_id, created_at as db_created_at, success as db_success, target_id as db_target_id, target_type as db_target_type};
+
+/// What an [`AuditEntry`] records
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    AccountCreate,
+    AccountUpdate,
+    AccountTokenRotate,
+    AccountDelete,
+    AccountEnable,
+    AccountDisable,
+    AccountDryRun,
+    GlobalDryRun,
+    MappingCreate,
+    MappingUpdate,
+    MappingDelete,
+    /// A [`BotCommand`] sent to one account, group commands record one entry per member
+    BotCommand,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::AccountCreate => "account_create",
+            AuditAction::AccountUpdate => "account_update",
+            AuditAction::AccountTokenRotate => "account_token_rotate",
+            AuditAction::AccountDelete => "account_delete",
+            AuditAction::AccountEnable => "account_enable",
+            AuditAction::AccountDisable => "account_disable",
+            AuditAction::AccountDryRun => "account_dry_run",
+            AuditAction::GlobalDryRun => "global_dry_run",
+            AuditAction::MappingCreate => "mapping_create",
+            AuditAction::MappingUpdate => "mapping_update",
+            AuditAction::MappingDelete => "mapping_delete",
+            AuditAction::BotCommand => "bot_command",
+        }
+    }
+}
+
+/// Kind of thing an [`AuditEntry`] acted on
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditTarget {
+    /// User signing in
+    User,
+    /// Controlled account, mapping changes target the account they belong to
+    Account,
+}
+
+impl AuditTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditTarget::User => "user",
+            AuditTarget::Account => "account",
+        }
+    }
+}
+
+/// Something a user, or a job on their behalf, did and whether it worked
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditEntry {
+    pub id: String,
+    /// `None` for failed sign in attempts
+    pub actor_id: Option<String>,
+    /// Name of the [`AuditAction`]
+    pub action: String,
+    /// Name of the [`AuditTarget`]
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// Address the request came from, `None` for scheduled jobs
+    pub ip: Option<String>,
+    /// `X-Forwarded-For` as sent, only trustworthy behind a proxy that sets it
+    pub forwarded_for: Option<String>,
+    pub user_agent: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+    pub details: Option<Value>,
+    created_at: NaiveDateTime,
+}
+
+/// Filters for [`AuditEntry::list_page`], unset filters match everything
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditFilter {
+    actor_id: Option<String>,
+    action: Option<AuditAction>,
+    target_type: Option<AuditTarget>,
+    target_id: Option<String>,
+    success: Option<bool>,
+    /// Entries created at or after
+    since: Option<DateTime<Utc>>,
+    /// Entries created before
+    until: Option<DateTime<Utc>>,
+}
+
+fn filtered(filter: &AuditFilter) -> audit_log::BoxedQuery<'static, Pg> {
+    let mut query = audit_log_table.into_boxed();
+    if let Some(actor) = &filter.actor_id {
+        query = query.filter(db_actor_id.eq(actor.clone()));
+    }
+    if let Some(action) = filter.action {
+        query = query.filter(db_action.eq(action.as_str()));
+    }
+    if let Some(target) = filter.target_type {
+        query = query.filter(db_target_type.eq(target.as_str()));
+    }
+    if let Some(target) = &filter.target_id {
+        query = query.filter(db_target_id.eq(target.clone()));
+    }
+    if let Some(success) = filter.success {
+        query = query.filter(db_success.eq(success));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(db_created_at.ge(since.naive_utc()));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(db_created_at.lt(until.naive_utc()));
+    }
+    query
+}
+
+impl AuditEntry {
+    /// Successful `action` by `actor_id`, see [`AuditEntry::result`] to record failures
+    pub fn new(action: AuditAction, actor_id: Option<String>) -> Self {
+        Self {
+            id: gen_id(),
+            actor_id,
+            action: action.as_str().to_string(),
+            target_type: None,
+            target_id: None,
+            ip: None,
+            forwarded_for: None,
+            user_agent: None,
+            success: true,
+            error: None,
+            details: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    /// `command` sent by `actor_id` to the controlled account
+    pub fn command(actor_id: String, account_id: String, command: &BotCommand) -> Self {
+        // the command itself can hold whole attachments so only what identifies it is kept
+        Self::new(AuditAction::BotCommand, Some(actor_id))
+            .target(AuditTarget::Account, account_id)
+            .detail("command", json!(command.name()))
+            .detail("guild_id", json!(command.guild_id()))
+    }
+
+    pub fn target(mut self, kind: AuditTarget, target_id: String) -> Self {
+        self.target_type = Some(kind.as_str().to_string());
+        self.target_id = Some(target_id);
+        self
+    }
+
+    pub fn request(mut self, meta: &RequestMeta) -> Self {
+        self.ip = meta.ip.clone();
+        self.forwarded_for = meta.forwarded_for.clone();
+        self.user_agent = meta.user_agent.clone();
+        self
+    }
+
+    /// Adds `key` to the entry's details object
+    pub fn detail(mut self, key: &str, value: Value) -> Self {
+        match &mut self.details {
+            Some(Value::Object(details)) => {
+                details.insert(key.to_string(), value);
+            },
+            _ => self.details = Some(json!({ key: value })),
+        }
+        self
+    }
+
+    pub fn outcome(mut self, success: bool, error: Option<String>) -> Self {
+        self.success = success;
+        self.error = error;
+        self
+    }
+
+    pub fn result<T>(self, result: &ApiResult<T>) -> Self {
+        self.outcome(result.is_ok(), result.as_ref().err().map(|e| e.to_string()))
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(audit_log_table).values(self).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Page of the entries matching `filter`, newest first, and the total count
+    pub async fn list_page(filter: &AuditFilter, limit: i64, offset: i64, conn: &mut DbConn) -> ApiResult<(Vec<AuditEntry>, i64)> {
+        let page = filtered(filter).order(db_created_at.desc()).limit(limit).offset(offset).load(conn).await.map_err(map_db_err)?;
+        let total = filtered(filter).count().get_result(conn).await.map_err(map_db_err)?;
+        Ok((page, total))
+    }
+}
@@ -1,66 +1,248 @@
-use diesel::{ExpressionMethods, Insertable, Queryable, Selectable};
-use diesel_async::RunQueryDsl;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel::result::DatabaseErrorKind;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use diesel_async::scoped_futures::ScopedFutureExt;
 use log::error;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
 use crate::api::DbConn;
-use crate::api::err::{ApiError, ApiResult};
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::conv_search_err;
 use crate::bots::account_client::BotClient;
-use crate::db::gen_id;
+use crate::discord_api::gateway::IdentifyOptions;
+use crate::discord_api::models::DiscordUser;
+use crate::discord_api::snowflake::UserId;
+use crate::discord_api::token::TokenKind;
 use crate::schema::controlled_account::dsl::controlled_account;
-use crate::schema::controlled_account::id;
+use crate::schema::controlled_account::{created_by as db_created_by, discord_id as db_discord_id, dry_run as db_dry_run, enabled as db_enabled, id, identify_options as db_identify_options, label as db_label, last_checked as db_last_checked, last_error as db_last_error, token as db_token, token_kind as db_token_kind, token_status as db_token_status, username as db_username};
+use crate::schemas::account_group::AccountGroup;
+use crate::schemas::account_mapping::AccountMapping;
+use crate::schemas::scheduled_job::ScheduledJob;
 
-#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable)]
+pub const TOKEN_VALID: &str = "valid";
+/// Discord rejected the token, the account's bot isn't run until a check passes again
+pub const TOKEN_INVALID: &str = "invalid";
+
+/// An account can only be added once per user
+fn map_write_err(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::BadRequest(String::from("Account was already added"))
+        },
+        _ => map_db_err(e)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
 #[diesel(table_name = crate::schema::controlled_account)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct ControlledAccount {
     /// ID for database
     pub id: String,
-    pub discord_id: String,
+    pub discord_id: UserId,
     pub username: String,
     #[serde(skip_serializing)]
     token: String,
-    created_by: String,
+    pub created_by: String,
+    /// Disabled accounts are kept but their bot isn't running
+    pub enabled: bool,
+    /// Bot only records outbound actions instead of sending them
+    pub dry_run: bool,
+    /// See [`TokenKind`]
+    token_kind: String,
+    /// Result of the last token health check, one of the `TOKEN_*` constants or `unchecked` for
+    /// accounts added before checks existed
+    pub token_status: String,
+    pub last_checked: Option<NaiveDateTime>,
+    /// Why the last check failed
+    pub last_error: Option<String>,
+    /// Name shown instead of the username
+    pub label: Option<String>,
+    /// See [`IdentifyOptions`]
+    identify_options: Option<Value>,
 }
 
 impl ControlledAccount {
     pub fn new(account_client: &BotClient) -> Self {
         Self {
-            id: gen_id(),
-            discord_id: account_client.account_id.clone(),
+            id: account_client.id.clone(),
+            discord_id: account_client.account_id,
             username: account_client.username.clone(),
             token: account_client.account_token.clone(),
             created_by: account_client.created_by.clone(),
+            enabled: true,
+            dry_run: account_client.dry_run.account_flag(),
+            token_kind: account_client.token_kind.as_str().to_string(),
+            // the token was just validated against Discord
+            token_status: TOKEN_VALID.to_string(),
+            last_checked: Some(Utc::now().naive_utc()),
+            last_error: None,
+            label: None,
+            identify_options: None,
         }
     }
 
+    pub fn token(&self) -> &str {
+        self.token.as_str()
+    }
+
+    pub fn token_kind(&self) -> TokenKind {
+        TokenKind::from_db(&self.token_kind)
+    }
+
+    pub fn identify_options(&self) -> IdentifyOptions {
+        self.identify_options.clone()
+            .and_then(|options| serde_json::from_value(options).ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the account's bot should be running
+    pub fn is_runnable(&self) -> bool {
+        self.enabled && self.token_status != TOKEN_INVALID
+    }
 
+    pub async fn list_all(conn: &mut DbConn) -> ApiResult<Vec<ControlledAccount>> {
+        controlled_account.load(conn).await.map_err(map_db_err)
+    }
+
+    /// Page of the accounts created by `uid` (every account when `None`) and the total count
+    pub async fn list_page(uid: Option<String>, limit: i64, offset: i64, conn: &mut DbConn) -> ApiResult<(Vec<ControlledAccount>, i64)> {
+        let (page, total) = match uid {
+            None => (
+                controlled_account.order(db_username.asc()).limit(limit).offset(offset).load(conn).await.map_err(map_db_err)?,
+                controlled_account.count().get_result(conn).await.map_err(map_db_err)?,
+            ),
+            Some(uid) => (
+                controlled_account.filter(db_created_by.eq(uid.clone())).order(db_username.asc()).limit(limit).offset(offset).load(conn).await.map_err(map_db_err)?,
+                controlled_account.filter(db_created_by.eq(uid)).count().get_result(conn).await.map_err(map_db_err)?,
+            ),
+        };
+        Ok((page, total))
+    }
+
+    pub async fn get_by_id(internal_id: String, conn: &mut DbConn) -> ApiResult<ControlledAccount> {
+        controlled_account.filter(id.eq(internal_id)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    /// Deletes account along with its group memberships, mappings and scheduled jobs, all or none
+    /// of them
     pub async fn delete_by_id(internal_id: String, conn: &mut DbConn) -> ApiResult<()> {
-        match diesel::delete(controlled_account).filter(id.eq(internal_id)).execute(conn).await {
-            Err(e) => {
-                match e {
-                    diesel::result::Error::NotFound => {
-                        Err(ApiError::NotFound)
-                    },
-                    _ => {
-                        error!("{e}");
-                        Err(ApiError::InternalError)
-                    }
-                }
-            },
-            Ok(_v) => Ok(())
+        conn.transaction::<_, ApiError, _>(|conn| async move {
+            if diesel::delete(controlled_account).filter(id.eq(internal_id.clone())).execute(conn).await.map_err(map_db_err)? == 0 {
+                return Err(ApiError::NotFound);
+            }
+            AccountGroup::remove_account(internal_id.clone(), conn).await?;
+            AccountMapping::delete_for_account(internal_id.clone(), conn).await?;
+            ScheduledJob::delete_for_account(internal_id, conn).await
+        }.scope_boxed()).await
+    }
 
-        }
+    pub async fn set_enabled(&mut self, enabled: bool, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set(db_enabled.eq(enabled))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.enabled = enabled;
+        Ok(())
+    }
+
+    pub async fn set_dry_run(&mut self, dry_run: bool, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set(db_dry_run.eq(dry_run))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.dry_run = dry_run;
+        Ok(())
+    }
+
+    pub async fn set_label(&mut self, label: Option<String>, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set(db_label.eq(label.clone()))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.label = label;
+        Ok(())
+    }
+
+    pub async fn set_identify_options(&mut self, options: &IdentifyOptions, conn: &mut DbConn) -> ApiResult<()> {
+        let options = serde_json::to_value(options).map_err(|e| {
+            error!("{e}");
+            ApiError::InternalError
+        })?;
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set(db_identify_options.eq(Some(options.clone())))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.identify_options = Some(options);
+        Ok(())
+    }
+
+    /// Replaces the account's token with one that was just validated against Discord
+    pub async fn rotate_token(&mut self, token: String, kind: TokenKind, conn: &mut DbConn) -> ApiResult<()> {
+        let now = Utc::now().naive_utc();
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set((
+                db_token.eq(token.clone()),
+                db_token_kind.eq(kind.as_str()),
+                db_token_status.eq(TOKEN_VALID),
+                db_last_checked.eq(Some(now)),
+                db_last_error.eq(None::<String>),
+            ))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.token = token;
+        self.token_kind = kind.as_str().to_string();
+        self.token_status = TOKEN_VALID.to_string();
+        self.last_checked = Some(now);
+        self.last_error = None;
+        Ok(())
+    }
+
+    pub async fn set_username(&mut self, username: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set(db_username.eq(username.clone()))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.username = username;
+        AccountMapping::sync_account(self, conn).await
+    }
+
+    /// Records a passed token check, picking up username or id changes Discord reported
+    pub async fn mark_token_valid(&mut self, user: &DiscordUser, conn: &mut DbConn) -> ApiResult<()> {
+        let now = Utc::now().naive_utc();
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set((
+                db_discord_id.eq(user.id),
+                db_username.eq(user.username.clone()),
+                db_token_status.eq(TOKEN_VALID),
+                db_last_checked.eq(Some(now)),
+                db_last_error.eq(None::<String>),
+            ))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.discord_id = user.id;
+        self.username = user.username.clone();
+        self.token_status = TOKEN_VALID.to_string();
+        self.last_checked = Some(now);
+        self.last_error = None;
+        AccountMapping::sync_account(self, conn).await
+    }
+
+    /// Records a failed token check, `status` is left as is when the failure says nothing about
+    /// the token itself (network errors, outages...)
+    pub async fn mark_token_check_failed(&mut self, status: Option<&str>, check_error: String, conn: &mut DbConn) -> ApiResult<()> {
+        let now = Utc::now().naive_utc();
+        let status = status.unwrap_or(self.token_status.as_str()).to_string();
+        diesel::update(controlled_account.filter(id.eq(self.id.clone())))
+            .set((
+                db_token_status.eq(status.clone()),
+                db_last_checked.eq(Some(now)),
+                db_last_error.eq(Some(check_error.clone())),
+            ))
+            .execute(conn).await.map_err(map_db_err)?;
+        self.token_status = status;
+        self.last_checked = Some(now);
+        self.last_error = Some(check_error);
+        Ok(())
     }
 
     pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
-        match diesel::insert_into(controlled_account).values(self).execute(conn).await {
-            Err(e) => {
-                error!("{e}");
-                Err(ApiError::InternalError)
-            },
-            Ok(_) => {
-                Ok(())
-            }
-        }
+        diesel::insert_into(controlled_account).values(self).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
     }
 }
@@ -0,0 +1,87 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel_async::RunQueryDsl;
+use log::error;
+use rand::random;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::api::DbConn;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::conv_search_err;
+use crate::schema::invite::dsl::invite;
+use crate::schema::invite::{code, created_at as db_created_at, created_by as db_created_by, expires_at as db_expires_at, used_at as db_used_at, used_by as db_used_by};
+
+/// Single use code a new user registers with
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::invite)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Invite {
+    pub code: String,
+    pub created_by: String,
+    /// Only this address can register with the invite, it's then considered verified
+    pub email: Option<String>,
+    /// `UserFlags` the new user gets
+    pub flags: i64,
+    created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_by: Option<String>,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl Invite {
+    pub fn new(created_by: String, email: Option<String>, flags: i64, valid_for: Duration) -> Self {
+        let now = Utc::now().naive_utc();
+        Self {
+            // longer than ids since knowing it is enough to register
+            code: hex::encode(random::<[u8; 16]>()),
+            created_by,
+            email,
+            flags,
+            created_at: now,
+            expires_at: now + valid_for,
+            used_by: None,
+            used_at: None,
+        }
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(invite).values(self).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn get_by_code(invite_code: String, conn: &mut DbConn) -> ApiResult<Invite> {
+        invite.filter(code.eq(invite_code)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    /// Invites created by `uid`, every invite when `None`
+    pub async fn list(uid: Option<String>, conn: &mut DbConn) -> ApiResult<Vec<Invite>> {
+        match uid {
+            None => invite.order(db_created_at.desc()).load(conn).await,
+            Some(uid) => invite.filter(db_created_by.eq(uid)).order(db_created_at.desc()).load(conn).await,
+        }.map_err(map_db_err)
+    }
+
+    pub fn is_usable(&self) -> bool {
+        self.used_by.is_none() && self.expires_at > Utc::now().naive_utc()
+    }
+
+    /// Marks the invite used by `uid`, fails if it was used or expired in the meantime so an
+    /// invite can't be redeemed twice
+    pub async fn claim(&mut self, uid: String, conn: &mut DbConn) -> ApiResult<()> {
+        let now = Utc::now().naive_utc();
+        let claimed = diesel::update(invite.filter(code.eq(self.code.clone()).and(db_used_by.is_null()).and(db_expires_at.gt(now))))
+            .set((db_used_by.eq(Some(uid.clone())), db_used_at.eq(Some(now))))
+            .execute(conn).await.map_err(map_db_err)?;
+        if claimed == 0 {
+            return Err(ApiError::BadRequest(String::from("Invite is invalid or expired")));
+        }
+        self.used_by = Some(uid);
+        self.used_at = Some(now);
+        Ok(())
+    }
+
+    pub async fn delete_by_code(invite_code: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::delete(invite.filter(code.eq(invite_code))).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+}
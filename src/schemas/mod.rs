@@ -1,6 +1,11 @@
 pub mod user;
 pub mod account_mapping;
 pub mod controlled_account;
+pub mod account_group;
+pub mod scheduled_job;
+pub mod webhook;
+pub mod invite;
+pub mod api_token;
+pub mod audit_log;
 
-pub use account_mapping::AccountMapping;
 pub use user::User;
\ No newline at end of file
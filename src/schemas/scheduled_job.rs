@@ -0,0 +1,219 @@
+use std::str::FromStr;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use cron::Schedule;
+use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::api::DbConn;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::bots::manager::BotCommand;
+use crate::conv_search_err;
+use crate::db::gen_id;
+use crate::schema::job_run::dsl::job_run;
+use crate::schema::job_run::{job_id, ran_at};
+use crate::schema::scheduled_job::dsl::scheduled_job;
+use crate::schema::scheduled_job::{account_id as db_account_id, created_by as db_created_by, enabled as db_enabled, id, next_run as db_next_run};
+
+/// Bot command that runs once at `run_at` or repeatedly following `cron` (UTC)
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::scheduled_job)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ScheduledJob {
+    pub id: String,
+    /// Controlled account the command is sent to
+    pub account_id: String,
+    command: Value,
+    pub run_at: Option<NaiveDateTime>,
+    pub cron: Option<String>,
+    /// `None` once a one-shot job has run
+    pub next_run: Option<NaiveDateTime>,
+    pub enabled: bool,
+    pub created_by: String,
+    created_at: NaiveDateTime,
+}
+
+/// Outcome of a single run of a [`ScheduledJob`]
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::job_run)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct JobRun {
+    id: String,
+    job_id: String,
+    ran_at: NaiveDateTime,
+    success: bool,
+    error: Option<String>,
+}
+
+fn parse_cron(expr: &str) -> ApiResult<Schedule> {
+    Schedule::from_str(expr).map_err(|e| ApiError::BadRequest(format!("Invalid cron expression: {e}")))
+}
+
+impl ScheduledJob {
+    /// Exactly one of `run_at` and `cron` must be set
+    pub fn new(account_id: String, command: &BotCommand, run_at: Option<DateTime<Utc>>, cron: Option<String>, created_by: String) -> ApiResult<Self> {
+        let next_run = match (&run_at, &cron) {
+            (Some(at), None) => Some(at.naive_utc()),
+            (None, Some(expr)) => match parse_cron(expr)?.upcoming(Utc).next() {
+                None => return Err(ApiError::BadRequest(String::from("Cron expression never fires"))),
+                Some(next) => Some(next.naive_utc())
+            },
+            _ => return Err(ApiError::BadRequest(String::from("Exactly one of run_at and cron must be set")))
+        };
+
+        Ok(Self {
+            id: gen_id(),
+            account_id,
+            command: serde_json::to_value(command).map_err(|e| {
+                error!("{e}");
+                ApiError::InternalError
+            })?,
+            run_at: run_at.map(|at| at.naive_utc()),
+            cron,
+            next_run,
+            enabled: true,
+            created_by,
+            created_at: Utc::now().naive_utc(),
+        })
+    }
+
+    pub fn command(&self) -> serde_json::Result<BotCommand> {
+        serde_json::from_value(self.command.clone())
+    }
+
+    /// Next time the job should run after `after`, `None` for one-shot jobs
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<NaiveDateTime> {
+        let schedule = Schedule::from_str(self.cron.as_ref()?.as_str()).ok()?;
+        schedule.after(&after).next().map(|next| next.naive_utc())
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(scheduled_job).values(self).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(job: String, conn: &mut DbConn) -> ApiResult<ScheduledJob> {
+        scheduled_job.filter(id.eq(job)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    pub async fn list_by_creator(uid: String, conn: &mut DbConn) -> ApiResult<Vec<ScheduledJob>> {
+        scheduled_job.filter(db_created_by.eq(uid)).order(db_next_run.asc()).load(conn).await.map_err(map_db_err)
+    }
+
+    /// Enabled jobs whose next run is at or before `now`
+    pub async fn get_due(now: DateTime<Utc>, conn: &mut DbConn) -> ApiResult<Vec<ScheduledJob>> {
+        scheduled_job
+            .filter(db_enabled.eq(true))
+            .filter(db_next_run.le(now.naive_utc()))
+            .load(conn).await.map_err(map_db_err)
+    }
+
+    pub async fn set_enabled(&mut self, enabled: bool, conn: &mut DbConn) -> ApiResult<()> {
+        // re-enabling a cron job shouldn't replay every run that was skipped while disabled
+        if enabled && !self.enabled && self.cron.is_some() {
+            self.next_run = self.next_after(Utc::now());
+        }
+        self.enabled = enabled;
+        diesel::update(scheduled_job.filter(id.eq(self.id.clone())))
+            .set((db_enabled.eq(self.enabled), db_next_run.eq(self.next_run)))
+            .execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Moves the job to its next run, one-shot jobs are disabled
+    pub async fn advance(&mut self, now: DateTime<Utc>, conn: &mut DbConn) -> ApiResult<()> {
+        self.next_run = self.next_after(now);
+        self.enabled = self.next_run.is_some();
+        diesel::update(scheduled_job.filter(id.eq(self.id.clone())))
+            .set((db_enabled.eq(self.enabled), db_next_run.eq(self.next_run)))
+            .execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Deletes job along with its run history, all or none of it
+    pub async fn delete_by_id(job: String, conn: &mut DbConn) -> ApiResult<()> {
+        conn.transaction::<_, ApiError, _>(|conn| async move {
+            diesel::delete(scheduled_job.filter(id.eq(job.clone()))).execute(conn).await.map_err(map_db_err)?;
+            diesel::delete(job_run.filter(job_id.eq(job))).execute(conn).await.map_err(map_db_err)?;
+            Ok(())
+        }.scope_boxed()).await
+    }
+
+    pub async fn delete_for_account(account: String, conn: &mut DbConn) -> ApiResult<()> {
+        let jobs: Vec<String> = scheduled_job.filter(db_account_id.eq(account)).select(id).load(conn).await.map_err(map_db_err)?;
+        for job in jobs {
+            ScheduledJob::delete_by_id(job, conn).await?;
+        }
+        Ok(())
+    }
+}
+
+impl JobRun {
+    pub fn new(job: &ScheduledJob, ran: DateTime<Utc>, result: &ApiResult<()>) -> Self {
+        Self {
+            id: gen_id(),
+            job_id: job.id.clone(),
+            ran_at: ran.naive_utc(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(job_run).values(self).execute(conn).await.map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Most recent runs first
+    pub async fn list_for_job(job: String, limit: i64, conn: &mut DbConn) -> ApiResult<Vec<JobRun>> {
+        job_run.filter(job_id.eq(job)).order(ran_at.desc()).limit(limit).load(conn).await.map_err(map_db_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use crate::discord_api::snowflake::{GuildId, Snowflake};
+    use super::*;
+
+    fn command() -> BotCommand {
+        BotCommand::SetSelfMute(GuildId(Snowflake(1)), true)
+    }
+
+    fn job(run_at: Option<DateTime<Utc>>, cron: Option<&str>) -> ApiResult<ScheduledJob> {
+        ScheduledJob::new(String::from("account"), &command(), run_at, cron.map(String::from), String::from("user"))
+    }
+
+    #[test]
+    fn needs_exactly_one_schedule() {
+        assert!(matches!(job(None, None), Err(ApiError::BadRequest(_))));
+        assert!(matches!(job(Some(Utc::now()), Some("0 0 12 * * *")), Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn one_shot_runs_at() {
+        let at = Utc.with_ymd_and_hms(2030, 1, 1, 8, 0, 0).unwrap();
+        let job = job(Some(at), None).unwrap();
+        assert_eq!(job.next_run, Some(at.naive_utc()));
+        assert_eq!(job.next_after(at), None);
+        assert!(matches!(job.command(), Ok(BotCommand::SetSelfMute(GuildId(Snowflake(1)), true))));
+    }
+
+    #[test]
+    fn rejects_invalid_cron() {
+        assert!(matches!(job(None, Some("not a cron")), Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn cron_next_after() {
+        let job = job(None, Some("0 0 12 * * *")).unwrap();
+        assert!(job.next_run.is_some());
+        let morning = Utc.with_ymd_and_hms(2030, 1, 1, 11, 0, 0).unwrap();
+        let noon = Utc.with_ymd_and_hms(2030, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(job.next_after(morning), Some(noon.naive_utc()));
+        assert_eq!(job.next_after(noon), Some(Utc.with_ymd_and_hms(2030, 1, 2, 12, 0, 0).unwrap().naive_utc()));
+    }
+}
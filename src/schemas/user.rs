@@ -1,18 +1,21 @@
+use axum::http::StatusCode;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::{Insertable, Queryable, Selectable};
+use diesel::result::DatabaseErrorKind;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use log::error;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
 use crate::api::DbConn;
-use crate::api::err::{ApiError, ApiResult};
+use crate::api::err::{map_db_err, ApiError, ApiResult};
 use crate::conv_search_err;
 use crate::db::gen_id;
 use crate::schema::users::dsl::users;
-use crate::schema::users::{id, username as db_username};
+use crate::schema::users::{created_at as db_created_at, email as db_email, id, password as db_password, username as db_username, verified_email as db_verified_email};
 
-#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable)]
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
@@ -35,8 +38,92 @@ pub struct User {
 pub const OWNER: i64 = 1 << 0;
 pub const STAFF: i64 = 1 << 1;
 
+/// Named things a user can be allowed to do, see [`ROLE_PERMISSIONS`] for who gets them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Act on every controlled account and its mappings, not only the ones the user created
+    ManageAccounts,
+    /// Create and revoke invites
+    ManageInvites,
+    /// List users and see every invite
+    ManageUsers,
+    /// Create invites that grant flags
+    GrantRoles,
+    /// Toggle dry-run for every bot
+    ManageGlobalDryRun,
+    /// Query the audit log
+    ViewAuditLog,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::ManageAccounts,
+        Permission::ManageInvites,
+        Permission::ManageUsers,
+        Permission::GrantRoles,
+        Permission::ManageGlobalDryRun,
+        Permission::ViewAuditLog,
+    ];
+}
+
+/// Permissions each flag grants, a user has the union of their flags' permissions
+pub const ROLE_PERMISSIONS: [(i64, &[Permission]); 2] = [
+    (OWNER, &Permission::ALL),
+    (STAFF, &[Permission::ManageAccounts, Permission::ManageInvites]),
+];
+
 const BCRYPT_COST: u32 = optimal_cost();
 
+const USERNAME_LENGTH: (usize, usize) = (3, 32);
+const MIN_PASSWORD_LENGTH: usize = 10;
+/// Longest address allowed by the SMTP spec
+const MAX_EMAIL_LENGTH: usize = 254;
+
+pub fn validate_username(username: &str) -> ApiResult<()> {
+    let (min, max) = USERNAME_LENGTH;
+    if username.len() < min || username.len() > max {
+        return Err(ApiError::BadRequest(format!("Username must be between {min} and {max} characters")));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || ['_', '-', '.'].contains(&c)) {
+        return Err(ApiError::BadRequest(String::from("Username can only contain letters, numbers, '_', '-' and '.'")));
+    }
+    Ok(())
+}
+
+pub fn validate_password(password: &str, username: &str) -> ApiResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::BadRequest(format!("Password must be at least {MIN_PASSWORD_LENGTH} characters")));
+    }
+    if !password.chars().any(|c| c.is_alphabetic()) || !password.chars().any(|c| !c.is_alphabetic()) {
+        return Err(ApiError::BadRequest(String::from("Password must contain a letter and a number or symbol")));
+    }
+    if password.to_lowercase().contains(&username.to_lowercase()) {
+        return Err(ApiError::BadRequest(String::from("Password can't contain the username")));
+    }
+    Ok(())
+}
+
+pub fn validate_email(email: &str) -> ApiResult<()> {
+    let valid = email.len() <= MAX_EMAIL_LENGTH && !email.contains(char::is_whitespace) && match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.'),
+        None => false,
+    };
+    if !valid {
+        return Err(ApiError::BadRequest(String::from("Invalid email address")));
+    }
+    Ok(())
+}
+
+fn map_write_err(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::BadRequest(String::from("Username is taken"))
+        },
+        _ => map_db_err(e)
+    }
+}
+
 /// Binary search for optimal cost depending on hardware. Shoots for 250ms hashing delay
 const fn optimal_cost() -> u32 {
     return 8;
@@ -63,15 +150,42 @@ impl User {
     }
 
     pub fn created_date(&self) -> DateTime<Utc> {
-        return self.created_at.and_utc().clone();
+        return self.created_at.and_utc();
+    }
+
+
+    /// Errors if the user is banned or kicked and may not use the api
+    pub fn check_standing(&self) -> ApiResult<()> {
+        if self.banned {
+            return Err(ApiError::Custom(
+                StatusCode::FORBIDDEN,
+                String::from("Banned"),
+                Some(String::from("Your account has been banned")),
+            ));
+        }
+        if self.kicked_until > Utc::now().naive_utc() {
+            return Err(ApiError::Custom(
+                StatusCode::FORBIDDEN,
+                String::from("Kicked"),
+                Some(format!("You can sign in again at {} UTC", self.kicked_until.format("%Y-%m-%d %H:%M"))),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn permissions(&self) -> Vec<Permission> {
+        Permission::ALL.into_iter().filter(|p| self.has_permission(*p)).collect()
     }
 
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        ROLE_PERMISSIONS.iter().any(|(flag, granted)| self.flags & flag != 0 && granted.contains(&permission))
+    }
 
     pub fn set_flag(&mut self, flag: i64, new_value: bool) -> &mut Self {
         if new_value {
-            self.flags = self.flags | flag;
+            self.flags |= flag;
         } else {
-            self.flags = self.flags ^ flag;
+            self.flags &= !flag;
         }
 
         return self;
@@ -84,4 +198,74 @@ impl User {
     pub async fn get_by_username(username: String, conn: &mut DbConn) -> ApiResult<User> {
         users.filter(db_username.eq(username)).first(conn).await.map_err(|e| conv_search_err!(e))
     }
-}
\ No newline at end of file
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(users).values(self).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn list_all(conn: &mut DbConn) -> ApiResult<Vec<User>> {
+        users.order(db_created_at.asc()).load(conn).await.map_err(map_db_err)
+    }
+
+    pub async fn set_password(&mut self, password: String, conn: &mut DbConn) -> ApiResult<()> {
+        let hashed_password = bcrypt::hash(Sha256::digest(password), BCRYPT_COST).map_err(|e| {
+            error!("{e}");
+            ApiError::InternalError
+        })?;
+        diesel::update(users.filter(id.eq(self.id.clone())))
+            .set(db_password.eq(hashed_password.clone()))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.password = hashed_password;
+        Ok(())
+    }
+
+    /// Changing the address resets its verification unless it's `verified` already
+    pub async fn set_email(&mut self, email: String, verified: bool, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(users.filter(id.eq(self.id.clone())))
+            .set((db_email.eq(email.clone()), db_verified_email.eq(verified)))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.email = email;
+        self.verified_email = verified;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames() {
+        assert!(validate_username("feeble_bot.1-2").is_ok());
+        assert!(validate_username("ab").is_err());
+        assert!(validate_username(&"a".repeat(33)).is_err());
+        assert!(validate_username("has space").is_err());
+        assert!(validate_username("ünicode").is_err());
+    }
+
+    #[test]
+    fn passwords() {
+        assert!(validate_password("correct horse 1", "feeble").is_ok());
+        assert!(validate_password("short1", "feeble").is_err());
+        assert!(validate_password("onlyletters", "feeble").is_err());
+        assert!(validate_password("1234567890", "feeble").is_err());
+    }
+
+    #[test]
+    fn password_cant_contain_username() {
+        assert!(validate_password("my-FEEBLE-password", "feeble").is_err());
+    }
+
+    #[test]
+    fn emails() {
+        assert!(validate_email("someone@example.com").is_ok());
+        assert!(validate_email("@example.com").is_err());
+        assert!(validate_email("someone@localhost").is_err());
+        assert!(validate_email("someone@.example.com").is_err());
+        assert!(validate_email("someone@example.com.").is_err());
+        assert!(validate_email("some one@example.com").is_err());
+        assert!(validate_email("no-at-sign").is_err());
+        assert!(validate_email(&format!("{}@example.com", "a".repeat(250))).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable};
+use diesel::result::DatabaseErrorKind;
+use diesel_async::RunQueryDsl;
+use log::error;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::api::DbConn;
+use crate::api::err::{map_db_err, ApiError, ApiResult};
+use crate::conv_search_err;
+use crate::db::gen_id;
+use crate::discord_api::snowflake::Snowflake;
+use crate::schema::webhook::dsl::webhook;
+use crate::schema::webhook::{created_by as db_created_by, discord_id as db_discord_id, encrypted_url as db_encrypted_url, id, name as db_name};
+use crate::util::secret::{decrypt, encrypt};
+use crate::webhook::WebhookClient;
+
+/// Discord webhook url saved for notifications, the url holds the webhook's token so it's only
+/// stored encrypted
+#[derive(Serialize, Deserialize, Clone, Debug, Selectable, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = crate::schema::webhook)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Webhook {
+    pub id: String,
+    pub name: String,
+    /// Discord's id of the webhook
+    pub discord_id: Snowflake,
+    #[serde(skip_serializing)]
+    encrypted_url: String,
+    pub created_by: String,
+    created_at: NaiveDateTime,
+}
+
+fn map_write_err(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            ApiError::BadRequest(String::from("A webhook with that name already exists"))
+        },
+        _ => map_db_err(e)
+    }
+}
+
+impl Webhook {
+    /// Validates and encrypts `url`
+    pub fn new(name: String, url: &str, created_by: String) -> ApiResult<Self> {
+        let client = WebhookClient::from_url(url)?;
+        Ok(Self {
+            id: gen_id(),
+            name,
+            discord_id: client.id,
+            encrypted_url: encrypt(url)?,
+            created_by,
+            created_at: Utc::now().naive_utc(),
+        })
+    }
+
+    pub fn client(&self) -> ApiResult<WebhookClient> {
+        WebhookClient::from_url(&decrypt(&self.encrypted_url)?)
+    }
+
+    pub async fn create(&self, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::insert_into(webhook).values(self).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(hook: String, conn: &mut DbConn) -> ApiResult<Webhook> {
+        webhook.filter(id.eq(hook)).first(conn).await.map_err(|e| conv_search_err!(e))
+    }
+
+    pub async fn list_by_creator(uid: String, conn: &mut DbConn) -> ApiResult<Vec<Webhook>> {
+        webhook.filter(db_created_by.eq(uid)).order(db_name.asc()).load(conn).await.map_err(map_db_err)
+    }
+
+    pub async fn rename(&mut self, new_name: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::update(webhook.filter(id.eq(self.id.clone())))
+            .set(db_name.eq(new_name.clone()))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.name = new_name;
+        Ok(())
+    }
+
+    /// Points the saved webhook at another url
+    pub async fn set_url(&mut self, url: &str, conn: &mut DbConn) -> ApiResult<()> {
+        let client = WebhookClient::from_url(url)?;
+        let encrypted_url = encrypt(url)?;
+        diesel::update(webhook.filter(id.eq(self.id.clone())))
+            .set((db_discord_id.eq(client.id), db_encrypted_url.eq(encrypted_url.clone())))
+            .execute(conn).await.map_err(map_write_err)?;
+        self.discord_id = client.id;
+        self.encrypted_url = encrypted_url;
+        Ok(())
+    }
+
+    pub async fn delete_by_id(hook: String, conn: &mut DbConn) -> ApiResult<()> {
+        diesel::delete(webhook.filter(id.eq(hook))).execute(conn).await.map_err(map_write_err)?;
+        Ok(())
+    }
+}
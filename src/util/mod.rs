@@ -1,4 +1,5 @@
 pub mod log;
+pub mod secret;
 
 pub fn strip_quotes(mut st: String) -> String {
     // suffix
@@ -0,0 +1,108 @@
+use std::env;
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use lazy_static::lazy_static;
+use log::error;
+use crate::api::err::{ApiError, ApiResult};
+
+/// Length of the nonce prepended to every ciphertext
+const NONCE_LEN: usize = 12;
+
+lazy_static! {
+    /// Key for secrets stored in the database, 32 hex encoded bytes in `SECRET_KEY`
+    static ref CIPHER: Option<Aes256Gcm> = env::var("SECRET_KEY").ok()
+        .and_then(|key| hex::decode(key).ok())
+        .filter(|key| key.len() == 32)
+        .map(|key| Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+}
+
+fn cipher() -> ApiResult<&'static Aes256Gcm> {
+    CIPHER.as_ref().ok_or_else(|| {
+        error!("SECRET_KEY isn't set or isn't 32 hex encoded bytes");
+        ApiError::InternalError
+    })
+}
+
+/// Encrypts `plain`, returns the hex encoded nonce followed by the ciphertext
+pub fn encrypt(plain: &str) -> ApiResult<String> {
+    seal(cipher()?, plain)
+}
+
+pub fn decrypt(sealed: &str) -> ApiResult<String> {
+    open(cipher()?, sealed)
+}
+
+fn seal(cipher: &Aes256Gcm, plain: &str) -> ApiResult<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(&nonce, plain.as_bytes()).map_err(|e| {
+        error!("{e}");
+        ApiError::InternalError
+    })?);
+    Ok(hex::encode(sealed))
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &str) -> ApiResult<String> {
+    let sealed = hex::decode(sealed).map_err(|e| {
+        error!("{e}");
+        ApiError::InternalError
+    })?;
+    if sealed.len() < NONCE_LEN {
+        error!("encrypted secret is too short");
+        return Err(ApiError::InternalError);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let plain = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|e| {
+        error!("{e}");
+        ApiError::InternalError
+    })?;
+    String::from_utf8(plain).map_err(|e| {
+        error!("{e}");
+        ApiError::InternalError
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cipher(byte: u8) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[byte; 32]))
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = test_cipher(1);
+        let sealed = seal(&cipher, "bot token").unwrap();
+        assert_ne!(sealed, "bot token");
+        assert_eq!(open(&cipher, &sealed).unwrap(), "bot token");
+    }
+
+    #[test]
+    fn fresh_nonce_each_time() {
+        let cipher = test_cipher(1);
+        assert_ne!(seal(&cipher, "bot token").unwrap(), seal(&cipher, "bot token").unwrap());
+    }
+
+    #[test]
+    fn rejects_tampered() {
+        let cipher = test_cipher(1);
+        let mut sealed = hex::decode(seal(&cipher, "bot token").unwrap()).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(open(&cipher, &hex::encode(sealed)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let cipher = test_cipher(1);
+        assert!(open(&cipher, "not hex").is_err());
+        assert!(open(&cipher, "00ff").is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let sealed = seal(&test_cipher(1), "bot token").unwrap();
+        assert!(open(&test_cipher(2), &sealed).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+use crate::api::err::{ApiError, ApiResult};
+use crate::discord_api::DiscordClient;
+use crate::discord_api::models::{AllowedMentions, EditMessage, Embed, Message};
+use crate::discord_api::snowflake::{ChannelId, MessageId, Snowflake};
+
+/// Hosts webhook urls are accepted from
+const WEBHOOK_HOSTS: [&str; 5] = ["discord.com", "discordapp.com", "canary.discord.com", "ptb.discord.com", "canary.discordapp.com"];
+
+/// Body of `POST /webhooks/{id}/{token}`, one of content or embeds is required
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ExecuteWebhook {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Overrides the webhook's default username
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Overrides the webhook's default avatar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embeds: Vec<Embed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_mentions: Option<AllowedMentions>,
+    /// Creates a thread with this name, forum channels only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_name: Option<String>,
+}
+
+/// Where and how a webhook message is sent
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebhookTarget {
+    /// Thread of the webhook's channel to post in
+    pub thread_id: Option<ChannelId>,
+    /// Wait for the message to be created and return it
+    #[serde(default)]
+    pub wait: bool,
+}
+
+/// Client for a single webhook, requests go through the shared unauthenticated [`DiscordClient`]
+#[derive(Clone, Debug)]
+pub struct WebhookClient {
+    api: DiscordClient,
+    pub id: Snowflake,
+    token: String,
+}
+
+impl WebhookClient {
+    /// Parses `https://discord.com/api/webhooks/{id}/{token}` (api version optional)
+    pub fn from_url(url: &str) -> ApiResult<Self> {
+        let invalid = || ApiError::BadRequest(String::from("Invalid webhook url"));
+        let rest = url.strip_prefix("https://").ok_or_else(invalid)?;
+        let (host, path) = rest.split_once('/').ok_or_else(invalid)?;
+        if !WEBHOOK_HOSTS.contains(&host) {
+            return Err(invalid());
+        }
+        let segments = path.trim_end_matches('/').split('/').collect::<Vec<_>>();
+        let (id, token) = match segments.as_slice() {
+            ["api", "webhooks", id, token] => (id, token),
+            ["api", version, "webhooks", id, token] if version.starts_with('v') => (id, token),
+            _ => return Err(invalid())
+        };
+        if token.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            api: DiscordClient::unauthenticated()?,
+            id: id.parse().map_err(|_| invalid())?,
+            token: token.to_string(),
+        })
+    }
+
+    fn route(&self, suffix: &str, thread_id: Option<ChannelId>, wait: bool) -> String {
+        let mut route = format!("/webhooks/{}/{}{}", self.id, self.token, suffix);
+        let mut query = vec![];
+        if wait {
+            query.push(String::from("wait=true"));
+        }
+        if let Some(thread_id) = thread_id {
+            query.push(format!("thread_id={}", thread_id));
+        }
+        if !query.is_empty() {
+            route.push('?');
+            route.push_str(&query.join("&"));
+        }
+        route
+    }
+
+    /// Posts message, returns it only when `target.wait` is set
+    pub async fn execute(&self, payload: &ExecuteWebhook, target: &WebhookTarget) -> ApiResult<Option<Message>> {
+        let route = self.route("", target.thread_id, target.wait);
+        self.api.request(Method::POST, &route, Some(payload)).await
+    }
+
+    pub async fn edit_message(&self, message_id: MessageId, edit: &EditMessage, thread_id: Option<ChannelId>) -> ApiResult<Message> {
+        let route = self.route(&format!("/messages/{}", message_id), thread_id, false);
+        self.api.request(Method::PATCH, &route, Some(edit)).await
+    }
+
+    pub async fn delete_message(&self, message_id: MessageId, thread_id: Option<ChannelId>) -> ApiResult<()> {
+        let route = self.route(&format!("/messages/{}", message_id), thread_id, false);
+        self.api.request(Method::DELETE, &route, None::<&()>).await
+    }
+}